@@ -1,18 +1,26 @@
 use core::str;
 use std::sync::Arc;
-use std::{ops::Deref, rc::Rc, sync::Weak};
+use std::{
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::Weak,
+};
 
 use cosmic::iced::advanced::graphics::text::Raw;
 use cosmic::iced::event::Status;
+use cosmic::iced::font::{Style as FontStyle, Weight as FontWeight};
 use cosmic::iced::keyboard::key::Named;
 use cosmic::iced::keyboard::Event as KeyEvent;
-use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::{Key, Location as KeyLocation, Modifiers};
 use cosmic::iced::mouse::{Event as MouseEvent, ScrollDelta};
 use cosmic::iced::Event;
+use cosmic::iced_core::clipboard::Kind as ClipboardKind;
 use cosmic::iced_core::renderer::Renderer as _;
 use cosmic::iced_core::text::Renderer as _;
 use cosmic::iced_renderer::graphics::text::cosmic_text::fontdb::Family;
 use cosmic::Renderer;
+
+use crate::app::scrollback::Scrollback;
 use cosmic::{
     iced::{
         alignment::{Horizontal, Vertical},
@@ -22,6 +30,7 @@ use cosmic::{
         layout,
         renderer::Quad,
         text::{LineHeight, Shaping, Wrapping},
+        widget::tree,
         Text,
     },
     iced_wgpu::graphics::text::cosmic_text::{
@@ -37,17 +46,118 @@ use unicode_width::UnicodeWidthChar;
 pub struct TerminalDisplay<Message> {
     // TODO: try the performance when the display bundle is in a fixed size array
     //
-    // TODO: add a visible column amount and visible row amount
-    pub cells: Vec<Vec<DisplayCell>>,
+    // the visible screen, the cursor position of the terminal is relative to it
+    pub cells: Vec<DisplayRow>,
+    // the scrollback of the terminal, shared instead of copied with the display
+    pub scrollback: Rc<Scrollback>,
     pub glyph_size: f32,
     pub font: String,
     pub line_height: f32,
     pub font_source: Source,
     pub font_index: u32,
-    pub on_input: Rc<Box<dyn Fn(char) -> Message>>,
+    pub on_input: Rc<Box<dyn Fn(KeyInput) -> Message>>,
     pub top_displaying_row: usize,
     pub visible_rows: usize,
+    pub visible_columns: usize,
     pub on_scroll: Rc<Box<dyn Fn(i8) -> Message>>,
+    // called instead of scrolling when the shell asked for mouse events
+    pub on_mouse: Rc<Box<dyn Fn(MouseInput) -> Message>>,
+    // called with the text of the clipboard on Ctrl+Shift+V, or of the primary selection on a
+    // middle click
+    pub on_paste: Rc<Box<dyn Fn(String) -> Message>>,
+    pub mouse_tracking: bool,
+    // position of the cursor on the screen, `None` when it is hidden
+    pub cursor: Option<(usize, usize)>,
+    // whether the window has focus, the cursor is drawn hollow without it
+    pub focused: bool,
+    // set by DECSCUSR (`CSI Ps SP q`)
+    pub cursor_shape: CursorShape,
+    pub cursor_blinking: bool,
+    // flipped by the blink timer, the cursor is drawn while it is set
+    pub cursor_blink_on: bool,
+    pub palette: ColorPalette,
+}
+
+// input that is kept between events, stored in the widget tree
+#[derive(Default)]
+struct InputState {
+    modifiers: Modifiers,
+    pressed_button: Option<MouseButton>,
+    // the cell the pointer was last reported on, motion within a cell is not reported
+    last_cell: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    // the pointer moved to another cell, with the button that is held down
+    Move(Option<MouseButton>),
+    WheelUp,
+    WheelDown,
+}
+
+// a key that the terminal knows how to send to the shell
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TerminalKey {
+    // a key that produces a character, with shift applied
+    Character(String),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    // F1 to F12
+    Function(u8),
+}
+
+// a key press or release, `numpad` is set for the keys of the keypad
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyInput {
+    pub key: TerminalKey,
+    // the character of the key without shift, the key code of the kitty keyboard protocol
+    pub base_key: Option<char>,
+    // the text the key produced, if any
+    pub text: Option<String>,
+    pub numpad: bool,
+    pub released: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
+}
+
+// a mouse event on a cell of the screen, sent to the shell when mouse tracking is enabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseInput {
+    pub action: MouseAction,
+    pub column: usize,
+    pub row: usize,
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
 }
 
 // a bundle is a grou pof cells that have the exact same style
@@ -67,23 +177,123 @@ pub struct DisplayBundle {
 pub struct DisplayCell {
     pub character: char,
     pub style: Option<DisplayStyle>,
+    // set on the blank cell a tab started from, so copying the line gives back the tab
+    pub tab: bool,
+}
+
+impl DisplayCell {
+    pub fn empty() -> Self {
+        Self {
+            character: ' ',
+            style: None,
+            tab: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.character == ' ' && self.style.is_none()
+    }
+}
+
+// a row of cells, `wrapped` is set when its line continues on the next row because the cursor
+// wrapped past the last column
+#[derive(Clone)]
+pub struct DisplayRow {
+    pub cells: Vec<DisplayCell>,
+    pub wrapped: bool,
+}
+
+impl DisplayRow {
+    pub fn new(columns: usize, cell: DisplayCell) -> Self {
+        Self {
+            cells: vec![cell; columns],
+            wrapped: false,
+        }
+    }
+
+    // whether the row has nothing printed or styled on it
+    pub fn is_blank(&self) -> bool {
+        !self.wrapped && self.cells.iter().all(DisplayCell::is_empty)
+    }
+}
+
+impl Deref for DisplayRow {
+    type Target = Vec<DisplayCell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cells
+    }
+}
+
+impl DerefMut for DisplayRow {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cells
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct DisplayStyle {
-    // TODO: terminal color management
-    pub background: Option<usize>,
-    pub foreground: Option<usize>,
+    pub background: TerminalColor,
+    pub foreground: TerminalColor,
+    // bit set of the `DisplayStyle::BOLD`, `DisplayStyle::ITALIC`, ... attributes
     pub style_metadata: usize,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TerminalColor {
+    // the default foreground or background, depending on where the color is used
+    #[default]
+    Default,
+    // one of the 16 colors set by `30..=37`, `40..=47`, `90..=97` and `100..=107`
+    Named(NamedColor),
+    // an entry of the 256 color palette, set by `38;5;n` and `48;5;n`
+    Indexed(u8),
+    // a 24 bit color, set by `38;2;r;g;b` and `48;2;r;g;b`
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NamedColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+// the colors every `TerminalColor` gets resolved with when drawing
+#[derive(Clone, Debug)]
+pub struct ColorPalette {
+    pub colors: [(u8, u8, u8); 256],
+    pub foreground: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+    // set by OSC 12, the cursor takes the default foreground without it
+    pub cursor: Option<(u8, u8, u8)>,
+    // reverse video (DECSCNM), the default foreground and background are swapped
+    pub reverse_video: bool,
+}
+
 impl<Message> TerminalDisplay<Message> {
     pub fn new(
         font_name: String,
         line_height: f32,
-        stdin_read: Box<dyn Fn(char) -> Message>,
+        stdin_read: Box<dyn Fn(KeyInput) -> Message>,
         on_scroll: Box<dyn Fn(i8) -> Message>,
+        on_mouse: Box<dyn Fn(MouseInput) -> Message>,
+        on_paste: Box<dyn Fn(String) -> Message>,
         rows: usize,
+        columns: usize,
     ) -> Self {
         let mut database = Database::new();
         database.load_system_fonts();
@@ -120,6 +330,7 @@ impl<Message> TerminalDisplay<Message> {
 
         Self {
             cells: Vec::new(),
+            scrollback: Rc::new(Scrollback::new(0)),
             font: font_name,
             glyph_size: get_glyph_size(font.source.clone(), font.index, ' ') * line_height + 0.05,
             font_source: font.source.clone(),
@@ -127,8 +338,252 @@ impl<Message> TerminalDisplay<Message> {
             line_height,
             top_displaying_row: 0,
             visible_rows: rows,
+            visible_columns: columns,
             on_scroll: Rc::new(on_scroll),
+            on_mouse: Rc::new(on_mouse),
+            on_paste: Rc::new(on_paste),
+            mouse_tracking: false,
+            cursor: Some((0, 0)),
+            focused: true,
+            cursor_shape: CursorShape::Block,
+            cursor_blinking: true,
+            cursor_blink_on: true,
             on_input: Rc::new(stdin_read),
+            palette: ColorPalette::default(),
+        }
+    }
+
+    // the rows and columns of cells that fit in the given bounds
+    pub fn grid_size(&self, bounds: Size) -> (usize, usize) {
+        let rows = (bounds.height / self.line_height).floor() as usize;
+        let columns = (bounds.width / self.glyph_size).floor() as usize;
+
+        (std::cmp::max(rows, 1), std::cmp::max(columns, 1))
+    }
+
+    // the cell under a point relative to the widget, clamped to the screen
+    pub fn cell_at(&self, position: Point) -> (usize, usize) {
+        let column = (position.x.max(0.0) / self.glyph_size) as usize;
+        let row = (position.y.max(0.0) / self.line_height) as usize;
+
+        (
+            std::cmp::min(column, self.visible_columns.saturating_sub(1)),
+            std::cmp::min(row, self.visible_rows.saturating_sub(1)),
+        )
+    }
+
+    // amount of rows in both the scrollback and the screen
+    pub fn total_rows(&self) -> usize {
+        self.scrollback.len() + self.cells.len()
+    }
+
+    // the rows shown in the widget, starting at `top_displaying_row`
+    pub fn displayed_rows(&self) -> impl Iterator<Item = &DisplayRow> {
+        let skipped_cells = self
+            .top_displaying_row
+            .saturating_sub(self.scrollback.len());
+
+        self.scrollback
+            .rows_from(self.top_displaying_row)
+            .chain(self.cells.iter().skip(skipped_cells))
+            .take(self.visible_rows)
+    }
+
+    // the column and row in the widget the cursor is drawn at, `None` when it is hidden or
+    // scrolled out of view
+    pub fn cursor_cell(&self) -> Option<(usize, usize)> {
+        let (column, row) = self.cursor?;
+        let row = (self.scrollback.len() + row).checked_sub(self.top_displaying_row)?;
+        if row >= self.visible_rows {
+            return None;
+        }
+
+        Some((
+            std::cmp::min(column, self.visible_columns.saturating_sub(1)),
+            row,
+        ))
+    }
+
+    // draws the cursor over the cell it is on in its shape, a block inverts the cell. without
+    // focus the cell is outlined whatever the shape
+    fn render_cursor(&self, renderer: &mut Renderer, view_position: Point) {
+        let Some((column, row)) = self.cursor_cell() else {
+            return;
+        };
+        let position = Point::new(
+            view_position.x + self.glyph_size * column as f32,
+            view_position.y + self.line_height * row as f32,
+        );
+        let cell = self
+            .displayed_rows()
+            .nth(row)
+            .and_then(|cells| cells.get(column))
+            .copied()
+            .unwrap_or(DisplayCell::empty());
+        let columns = cell.character.width().unwrap_or(1).max(1);
+        let width = self.glyph_size * columns as f32;
+        let (r, g, b) = self
+            .palette
+            .cursor
+            .unwrap_or(self.palette.default_foreground());
+        let color = Color::from_rgb8(r, g, b);
+
+        if !self.focused {
+            renderer.fill_quad(
+                Quad {
+                    bounds: Rectangle::new(position, Size::new(width, self.line_height)),
+                    border: Border {
+                        color,
+                        width: 1.0,
+                        radius: Radius::from(0.0),
+                    },
+                    ..Default::default()
+                },
+                Color::TRANSPARENT,
+            );
+            return;
+        }
+
+        // a blinking cursor is not drawn for the off half of the blink
+        if self.cursor_blinking && !self.cursor_blink_on {
+            return;
+        }
+
+        match self.cursor_shape {
+            CursorShape::Block => {
+                let mut style = cell.style.unwrap_or(DisplayStyle::none());
+                let ((r, g, b), (text_r, text_g, text_b)) = match self.palette.cursor {
+                    // the text takes the background color so it stays readable on the cursor
+                    Some(cursor) => (cursor, self.palette.default_background()),
+                    // the colors the cell is drawn with are swapped, so a cell that is already
+                    // inverted still shows the cursor
+                    None => {
+                        let (text_color, background_color) = self.palette.style_colors(&style);
+                        let [r, g, b, _] = text_color.into_rgba8();
+                        let [text_r, text_g, text_b, _] = match background_color {
+                            Some(color) => color.into_rgba8(),
+                            None => {
+                                let (r, g, b) = self.palette.default_background();
+                                [r, g, b, 255]
+                            }
+                        };
+                        ((r, g, b), (text_r, text_g, text_b))
+                    }
+                };
+                style.style_metadata &= !(DisplayStyle::INVERSE | DisplayStyle::DIM);
+                style.background = TerminalColor::Rgb(r, g, b);
+                style.foreground = TerminalColor::Rgb(text_r, text_g, text_b);
+                self.render_cell(
+                    renderer,
+                    position,
+                    columns,
+                    cell.character.to_string(),
+                    Some(style),
+                );
+            }
+            CursorShape::Underline => {
+                let thickness = (self.line_height / 10.0).max(1.0);
+                renderer.fill_quad(
+                    Quad {
+                        bounds: Rectangle::new(
+                            Point::new(position.x, position.y + self.line_height - thickness),
+                            Size::new(width, thickness),
+                        ),
+                        ..Default::default()
+                    },
+                    color,
+                );
+            }
+            CursorShape::Bar => {
+                let thickness = (self.glyph_size / 6.0).max(1.0);
+                renderer.fill_quad(
+                    Quad {
+                        bounds: Rectangle::new(position, Size::new(thickness, self.line_height)),
+                        ..Default::default()
+                    },
+                    color,
+                );
+            }
+        }
+    }
+
+    // draws `columns` cells worth of text starting at `position`, along with the background and
+    // the decorations of its style
+    fn render_cell(
+        &self,
+        renderer: &mut Renderer,
+        position: Point,
+        columns: usize,
+        content: String,
+        style: Option<DisplayStyle>,
+    ) {
+        let style = style.unwrap_or(DisplayStyle::none());
+        let size = Size::new(self.glyph_size * columns as f32, self.line_height);
+        let (text_color, background_color) = self.palette.style_colors(&style);
+
+        if let Some(background_color) = background_color {
+            renderer.fill_quad(
+                Quad {
+                    bounds: Rectangle::new(position, size),
+                    ..Default::default()
+                },
+                background_color,
+            );
+        }
+
+        if style.has(DisplayStyle::HIDDEN) {
+            return;
+        }
+
+        let mut font = unsafe { Font::with_name(make_static_str(&self.font)) };
+        if style.has(DisplayStyle::BOLD) {
+            font.weight = FontWeight::Bold;
+        }
+        if style.has(DisplayStyle::ITALIC) {
+            font.style = FontStyle::Italic;
+        }
+
+        // text bounds are a bit wider than the cells so the last glyph does not get clipped
+        let text_size = Size::new(self.glyph_size * (columns + 2) as f32, self.line_height);
+        renderer.fill_text(
+            Text {
+                size: Pixels(self.line_height),
+                line_height: LineHeight::Absolute(Pixels(self.line_height)),
+                bounds: text_size,
+                font,
+                horizontal_alignment: Horizontal::Left,
+                vertical_alignment: Vertical::Top,
+                wrapping: Wrapping::None,
+                shaping: Shaping::Advanced, // might need advanced?
+                content,
+            },
+            position,
+            text_color,
+            Rectangle::new(position, text_size), // clip bounds
+        );
+
+        let mut decoration = |offset: f32| {
+            renderer.fill_quad(
+                Quad {
+                    bounds: Rectangle::new(
+                        Point::new(position.x, position.y + offset),
+                        Size::new(size.width, 1.0),
+                    ),
+                    ..Default::default()
+                },
+                text_color,
+            );
+        };
+
+        // TODO: draw curly, dotted and dashed underlines with their own shape
+        if style.has(DisplayStyle::ALL_UNDERLINES) {
+            decoration(self.line_height - 2.0);
+        }
+        if style.has(DisplayStyle::DOUBLE_UNDERLINE) {
+            decoration(self.line_height - 4.0);
+        }
+        if style.has(DisplayStyle::STRIKETHROUGH) {
+            decoration(self.line_height / 2.0);
         }
     }
 }
@@ -165,19 +620,207 @@ where
 }
 
 impl DisplayStyle {
+    pub const BOLD: usize = 1 << 0;
+    pub const DIM: usize = 1 << 1;
+    pub const ITALIC: usize = 1 << 2;
+    pub const UNDERLINE: usize = 1 << 3;
+    pub const DOUBLE_UNDERLINE: usize = 1 << 4;
+    pub const CURLY_UNDERLINE: usize = 1 << 5;
+    pub const DOTTED_UNDERLINE: usize = 1 << 6;
+    pub const DASHED_UNDERLINE: usize = 1 << 7;
+    pub const BLINK: usize = 1 << 8;
+    pub const RAPID_BLINK: usize = 1 << 9;
+    pub const INVERSE: usize = 1 << 10;
+    pub const HIDDEN: usize = 1 << 11;
+    pub const STRIKETHROUGH: usize = 1 << 12;
+
+    pub const ALL_UNDERLINES: usize = Self::UNDERLINE
+        | Self::DOUBLE_UNDERLINE
+        | Self::CURLY_UNDERLINE
+        | Self::DOTTED_UNDERLINE
+        | Self::DASHED_UNDERLINE;
+
     pub fn none() -> Self {
         Self {
-            background: None,
-            foreground: None,
+            background: TerminalColor::Default,
+            foreground: TerminalColor::Default,
             style_metadata: 0,
         }
     }
+
+    pub fn has(&self, attribute: usize) -> bool {
+        self.style_metadata & attribute != 0
+    }
+
+    pub fn set(&mut self, attribute: usize, enabled: bool) {
+        if enabled {
+            self.style_metadata |= attribute;
+        } else {
+            self.style_metadata &= !attribute;
+        }
+    }
+}
+
+impl NamedColor {
+    const ALL: [NamedColor; 16] = [
+        NamedColor::Black,
+        NamedColor::Red,
+        NamedColor::Green,
+        NamedColor::Yellow,
+        NamedColor::Blue,
+        NamedColor::Magenta,
+        NamedColor::Cyan,
+        NamedColor::White,
+        NamedColor::BrightBlack,
+        NamedColor::BrightRed,
+        NamedColor::BrightGreen,
+        NamedColor::BrightYellow,
+        NamedColor::BrightBlue,
+        NamedColor::BrightMagenta,
+        NamedColor::BrightCyan,
+        NamedColor::BrightWhite,
+    ];
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    // the bright variant of one of the first 8 colors, used to draw bold text
+    pub fn bright(&self) -> Self {
+        Self::ALL[self.index() | 8]
+    }
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        let mut colors = [(0, 0, 0); 256];
+
+        colors[..16].copy_from_slice(&[
+            (0x00, 0x00, 0x00),
+            (0xcd, 0x00, 0x00),
+            (0x00, 0xcd, 0x00),
+            (0xcd, 0xcd, 0x00),
+            (0x00, 0x00, 0xee),
+            (0xcd, 0x00, 0xcd),
+            (0x00, 0xcd, 0xcd),
+            (0xe5, 0xe5, 0xe5),
+            (0x7f, 0x7f, 0x7f),
+            (0xff, 0x00, 0x00),
+            (0x00, 0xff, 0x00),
+            (0xff, 0xff, 0x00),
+            (0x5c, 0x5c, 0xff),
+            (0xff, 0x00, 0xff),
+            (0x00, 0xff, 0xff),
+            (0xff, 0xff, 0xff),
+        ]);
+
+        // 6x6x6 color cube
+        let levels = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+        for index in 0..216 {
+            colors[16 + index] = (
+                levels[index / 36],
+                levels[(index / 6) % 6],
+                levels[index % 6],
+            );
+        }
+
+        // grayscale ramp
+        for index in 0..24 {
+            let level = 8 + 10 * index as u8;
+            colors[232 + index] = (level, level, level);
+        }
+
+        Self {
+            colors,
+            foreground: (0xff, 0xff, 0xff),
+            background: (0x00, 0x00, 0x00),
+            cursor: None,
+            reverse_video: false,
+        }
+    }
+}
+
+impl ColorPalette {
+    pub fn default_foreground(&self) -> (u8, u8, u8) {
+        match self.reverse_video {
+            true => self.background,
+            false => self.foreground,
+        }
+    }
+
+    pub fn default_background(&self) -> (u8, u8, u8) {
+        match self.reverse_video {
+            true => self.foreground,
+            false => self.background,
+        }
+    }
+
+    pub fn resolve(&self, color: TerminalColor, default: (u8, u8, u8)) -> Color {
+        let (r, g, b) = match color {
+            TerminalColor::Default => default,
+            TerminalColor::Named(named) => self.colors[named.index()],
+            TerminalColor::Indexed(index) => self.colors[index as usize],
+            TerminalColor::Rgb(r, g, b) => (r, g, b),
+        };
+
+        Color::from_rgb8(r, g, b)
+    }
+
+    // returns the text color and the background color of a style, the background being `None`
+    // when the default background should show through
+    pub fn style_colors(&self, style: &DisplayStyle) -> (Color, Option<Color>) {
+        let mut foreground = style.foreground;
+        let mut background = style.background;
+
+        if style.has(DisplayStyle::BOLD) {
+            if let TerminalColor::Named(named) = foreground {
+                foreground = TerminalColor::Named(named.bright());
+            }
+        }
+
+        let (foreground_default, background_default) = if style.has(DisplayStyle::INVERSE) {
+            std::mem::swap(&mut foreground, &mut background);
+            (self.default_background(), self.default_foreground())
+        } else {
+            (self.default_foreground(), self.default_background())
+        };
+
+        let mut text_color = self.resolve(foreground, foreground_default);
+        if style.has(DisplayStyle::DIM) {
+            text_color = Color::from_rgb(
+                text_color.r * 0.66,
+                text_color.g * 0.66,
+                text_color.b * 0.66,
+            );
+        }
+
+        let background_color =
+            if background == TerminalColor::Default && !style.has(DisplayStyle::INVERSE) {
+                None
+            } else {
+                Some(self.resolve(background, background_default))
+            };
+
+        (text_color, background_color)
+    }
 }
 
 impl<Message> Widget<Message, cosmic::Theme, Renderer> for TerminalDisplay<Message>
 where
     Message: Clone,
 {
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<InputState>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(InputState::default())
+    }
+
     fn layout(
         &self,
         tree: &mut cosmic::iced_core::widget::Tree,
@@ -209,185 +852,84 @@ where
         // TODO: add some padding later
         let view_position = layout.position();
 
-        // TODO: add code that renders the default background, mainly used for cells that do not
-        // have a background specified
-        {}
-
-        let mut previous_style: Option<DisplayStyle> = None;
+        // default background, used by every cell that does not have a background specified
+        let (r, g, b) = self.palette.default_background();
+        renderer.fill_quad(
+            Quad {
+                bounds: layout.bounds(),
+                ..Default::default()
+            },
+            Color::from_rgb8(r, g, b),
+        );
 
-        let mut render_cell = |position, size, content| {
-            renderer.fill_quad(
-                Quad {
-                    bounds: Rectangle::new(position, size),
-                    // ..Default::default()
-                    border: Border {
-                        color: Color::new(0.0, 1.0, 0.0, 0.2),
-                        width: 1.0,
-                        radius: Radius::new(0),
-                    },
-                    ..Default::default()
-                },
-                Color::new(1.0, 0.0, 0.0, 0.4),
-            );
-            // }
-            renderer.fill_text(
-                Text {
-                    // content: bundle.characters.iter().map(ToString::to_string).collect(),
-                    size: Pixels(self.line_height),
-                    line_height: LineHeight::Absolute(Pixels(self.line_height)),
-                    // bounds: Size::new(layout.bounds().width, self.line_height),
-                    bounds: size,
-                    font: unsafe { Font::with_name(make_static_str(&self.font)) }, //"ProggyClean CE Nerd Font"),
-                    horizontal_alignment: Horizontal::Left,
-                    vertical_alignment: Vertical::Top,
-                    wrapping: Wrapping::None,
-                    shaping: Shaping::Advanced, // might need advanced?
-                    content,
-                },
-                position,
-                // Point {
-                //     x: view_position.x + (self.glyph_size * bundle.character_start as f32),
-                //     y: view_position.y + (self.line_height * index as f32  )
-                // }, // position
-                Color::new(1.0, 1.0, 1.0, 1.0), // TODO
-                Rectangle::new(position, size), // clip bounds
-            );
-        };
+        // ascii cells with the same style are drawn together as a single bundle
         let mut bundle_text: Vec<char> = Vec::new();
+        let mut bundle_style: Option<DisplayStyle> = None;
+        let mut bundle_column = 0;
 
         println!("top displaying row is: {:?}", self.top_displaying_row);
         // TODO: try performance with just regular mutable index
-        for (index_y, row) in self.cells[self.top_displaying_row..]
-            .iter()
-            .take(self.visible_rows)
-            .enumerate()
-        {
-            // println!(
-            //     "render: {:?}",
-            //     String::from_iter(row.iter().map(|cell| cell.character))
-            // );
-            let mut offset = 0;
-            let len = row.len();
-            for (index_x, cell) in row.iter().enumerate() {
-                if cell.character.is_ascii() {
-                    if previous_style == cell.style {
-                        bundle_text.push(cell.character);
-                        if index_x != len - 1 {
-                            continue;
-                        }
-                    }
+        for (index_y, row) in self.displayed_rows().enumerate() {
+            let y = view_position.y + self.line_height * index_y as f32;
+            // the column on screen, wide characters take up more than one
+            let mut column = 0;
 
-                    if bundle_text.is_empty() {
-                        render_cell(
-                            Point {
-                                x: view_position.x + self.glyph_size * (index_x + offset) as f32,
-                                y: view_position.y + ((self.line_height) * index_y as f32),
-                            },
-                            Size::new(self.glyph_size, self.line_height),
-                            cell.character.to_string(),
-                        );
-                    } else {
-                        // println!("index_x is: {:?}", index_x);
-                        // println!("bundle_text len: {:?}", bundle_text.len());
-                        // println!(
-                        //     "bundle text contains: {:?}",
-                        //     String::from_iter(bundle_text.iter())
-                        // );
-                        render_cell(
-                            Point {
-                                x: view_position.x
-                                    + self.glyph_size
-                                        * (index_x + 1 - bundle_text.len() + offset) as f32,
-                                y: view_position.y + ((self.line_height) * index_y as f32),
-                            },
-                            Size::new(
-                                self.glyph_size * (bundle_text.len() + 2) as f32,
-                                self.line_height,
-                            ),
-                            String::from_iter(bundle_text.iter()),
+            for cell in row.iter() {
+                if cell.character.is_ascii() {
+                    if !bundle_text.is_empty() && bundle_style != cell.style {
+                        self.render_cell(
+                            renderer,
+                            Point::new(view_position.x + self.glyph_size * bundle_column as f32, y),
+                            bundle_text.len(),
+                            String::from_iter(bundle_text.drain(..)),
+                            bundle_style,
                         );
-                        bundle_text.clear();
-                        previous_style = None;
                     }
-                } else {
-                    // render previous bundle if there is one
-                    if !bundle_text.is_empty() {
-                        // println!("index_x is: {:?}", index_x);
-                        // println!("bundle_text len: {:?}", bundle_text.len());
-                        render_cell(
-                            Point {
-                                x: view_position.x
-                                    + self.glyph_size
-                                        * (index_x + offset - bundle_text.len()) as f32,
-                                y: view_position.y + ((self.line_height) * index_y as f32),
-                            },
-                            Size::new(self.glyph_size * bundle_text.len() as f32, self.line_height),
-                            String::from_iter(bundle_text.iter()),
-                        );
-                        bundle_text.clear();
+                    if bundle_text.is_empty() {
+                        bundle_column = column;
+                        bundle_style = cell.style;
                     }
 
-                    let char_width = cell.character.width().unwrap_or(1);
+                    bundle_text.push(cell.character);
+                    column += 1;
+                    continue;
+                }
 
-                    render_cell(
-                        Point {
-                            x: view_position.x + self.glyph_size * (index_x + offset) as f32,
-                            y: view_position.y + ((self.line_height) * index_y as f32),
-                        },
-                        Size::new(self.glyph_size * char_width as f32, self.line_height),
-                        cell.character.to_string(),
+                // render previous bundle if there is one
+                if !bundle_text.is_empty() {
+                    self.render_cell(
+                        renderer,
+                        Point::new(view_position.x + self.glyph_size * bundle_column as f32, y),
+                        bundle_text.len(),
+                        String::from_iter(bundle_text.drain(..)),
+                        bundle_style,
                     );
-                    offset += char_width - 1;
-                    previous_style = None;
                 }
 
-                // if cell.character.is_ascii() {
-                //     println!("got character: {:?}", cell.character);
-                //     let BundleOrChar::Bundle(ref mut bundle) = bundle_text else {
-                //         panic!("bundle text changed to character")
-                //     };
-                //     if previous_style == cell.style {
-                //         bundle[bundle.len()] = cell.character;
-                //         if index_x != len {
-                //             continue;
-                //         }
-                //     }
-                //     let position = Point {
-                //         x: view_position.x + self.glyph_size * (index_x + offset) as f32,
-                //         // x: view_position.x + offset,
-                //         y: view_position.y + ((self.line_height) * index_y as f32),
-                //     };
-                //     previous_style = cell.style;
-                // } else {
-                //     let char_width = cell.character.width().unwrap_or(1);
-                //     offset += char_width;
-                //     previous_style = None;
-                //
-                //     render_cell(
-                //         char_width,
-                //         // position
-                //         Point {
-                //             x: view_position.x + self.glyph_size * (index_x + offset) as f32,
-                //             // x: view_position.x + offset,
-                //             y: view_position.y + ((self.line_height) * index_y as f32),
-                //         },
-                //         BundleOrChar::Char(cell.character),
-                //     )
-                // }
-                //
-                // // offset += glyph_size * (bundle.character_start) as f32;
-                //
-                // let BundleOrChar::Bundle(ref mut bundle) = bundle_text else {
-                //     panic!("bundleorchar is char!")
-                // };
-                // bundle.clear();
-                //
-                // renderer.fill_text(text, position, color, clip_bounds);
-                // unicode_offset += offset;
+                let char_width = cell.character.width().unwrap_or(1);
+                self.render_cell(
+                    renderer,
+                    Point::new(view_position.x + self.glyph_size * column as f32, y),
+                    char_width,
+                    cell.character.to_string(),
+                    cell.style,
+                );
+                column += char_width;
+            }
+
+            if !bundle_text.is_empty() {
+                self.render_cell(
+                    renderer,
+                    Point::new(view_position.x + self.glyph_size * bundle_column as f32, y),
+                    bundle_text.len(),
+                    String::from_iter(bundle_text.drain(..)),
+                    bundle_style,
+                );
             }
-            bundle_text.clear();
         }
 
+        self.render_cursor(renderer, view_position);
+
         // let scrollbar_w = f32::from(cosmic_theme.spacing.space_xxs);
     }
 
@@ -405,53 +947,155 @@ where
     // }
     fn on_event(
         &mut self,
-        _state: &mut cosmic::iced_core::widget::Tree,
+        tree: &mut cosmic::iced_core::widget::Tree,
         event: cosmic::iced::Event,
         layout: layout::Layout<'_>,
         cursor: cosmic::iced_core::mouse::Cursor,
         _renderer: &Renderer,
-        _clipboard: &mut dyn cosmic::iced_core::Clipboard,
+        clipboard: &mut dyn cosmic::iced_core::Clipboard,
         shell: &mut cosmic::iced_core::Shell<'_, Message>,
         _viewport: &Rectangle,
     ) -> Status {
+        let state = tree.state.downcast_mut::<InputState>();
+        if let Event::Keyboard(KeyEvent::ModifiersChanged(modifiers)) = event {
+            state.modifiers = modifiers;
+        }
+
+        // the shell gets the mouse events instead of them being handled here
+        if self.mouse_tracking {
+            let bounds = layout.bounds();
+            let position = cursor.position_in(bounds);
+            let action = match event {
+                Event::Mouse(MouseEvent::ButtonPressed(button)) => {
+                    mouse_button(button).map(MouseAction::Press)
+                }
+                Event::Mouse(MouseEvent::ButtonReleased(button)) => {
+                    mouse_button(button).map(MouseAction::Release)
+                }
+                Event::Mouse(MouseEvent::CursorMoved { .. }) => {
+                    Some(MouseAction::Move(state.pressed_button))
+                }
+                Event::Mouse(MouseEvent::WheelScrolled { delta }) => match delta {
+                    ScrollDelta::Lines { y, .. } | ScrollDelta::Pixels { y, .. } if y > 0.0 => {
+                        Some(MouseAction::WheelUp)
+                    }
+                    ScrollDelta::Lines { y, .. } | ScrollDelta::Pixels { y, .. } if y < 0.0 => {
+                        Some(MouseAction::WheelDown)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            // releases are still reported outside of the widget so that drags can end
+            let position = match (action, position) {
+                (Some(MouseAction::Release(_)), None) => cursor
+                    .position()
+                    .map(|position| Point::new(position.x - bounds.x, position.y - bounds.y)),
+                (_, position) => position,
+            };
+
+            if let (Some(action), Some(position)) = (action, position) {
+                let (column, row) = self.cell_at(position);
+                let input = MouseInput {
+                    action,
+                    column,
+                    row,
+                    shift: state.modifiers.shift(),
+                    alt: state.modifiers.alt(),
+                    control: state.modifiers.control(),
+                };
+                let cell = Some((column, row));
+
+                let report = match action {
+                    MouseAction::Press(button) => {
+                        state.pressed_button = Some(button);
+                        true
+                    }
+                    MouseAction::Release(_) => {
+                        state.pressed_button = None;
+                        true
+                    }
+                    MouseAction::Move(_) => state.last_cell != cell,
+                    MouseAction::WheelUp | MouseAction::WheelDown => true,
+                };
+                state.last_cell = cell;
+
+                if report {
+                    shell.publish(self.on_mouse.clone()(input));
+                }
+                return Status::Captured;
+            }
+        }
+
         match event {
             Event::Keyboard(KeyEvent::KeyPressed {
-                key: Key::Named(named),
-                modified_key: Key::Named(modified_name),
+                key: Key::Character(ref character),
                 modifiers,
-                text,
                 ..
-            }) => match named {
-                Named::Enter => {
-                    shell.publish(self.on_input.clone()('\x0D'));
-                    return Status::Captured;
+            }) if modifiers.control()
+                && modifiers.shift()
+                && character.eq_ignore_ascii_case("v") =>
+            {
+                if let Some(text) = clipboard.read(ClipboardKind::Standard) {
+                    shell.publish(self.on_paste.clone()(text));
                 }
-                Named::Space => {
-                    shell.publish(self.on_input.clone()(
-                        text.and_then(|c| c.chars().next()).unwrap_or_default(),
-                    ));
-
-                    return Status::Captured;
+                return Status::Captured;
+            }
+            Event::Mouse(MouseEvent::ButtonPressed(cosmic::iced::mouse::Button::Middle))
+                if cursor.is_over(layout.bounds()) =>
+            {
+                if let Some(text) = clipboard.read(ClipboardKind::Primary) {
+                    shell.publish(self.on_paste.clone()(text));
                 }
-                _ => (),
-            },
-            // }) if named == modified_name => match named {
-            //     _ => Status::Ignored,
-            // },
+                return Status::Captured;
+            }
             Event::Keyboard(KeyEvent::KeyPressed {
                 key,
                 modified_key,
-                physical_key,
                 location,
                 modifiers,
                 text,
+                ..
+            }) => {
+                let Some(terminal_key) = terminal_key(&modified_key) else {
+                    return Status::Ignored;
+                };
+
+                shell.publish(self.on_input.clone()(KeyInput {
+                    key: terminal_key,
+                    base_key: base_key(&key),
+                    text: text.map(|text| text.to_string()),
+                    numpad: location == KeyLocation::Numpad,
+                    released: false,
+                    shift: modifiers.shift(),
+                    alt: modifiers.alt(),
+                    control: modifiers.control(),
+                }));
+                return Status::Captured;
+            }
+            // releases are only sent by the kitty keyboard protocol, the key encoder decides
+            Event::Keyboard(KeyEvent::KeyReleased {
+                key,
+                location,
+                modifiers,
+                ..
             }) => {
-                let character = text.and_then(|c| c.chars().next()).unwrap_or_default();
-                // if let Some(input) = &self.on_input {
-                // shell.publish(self.on_input.clone());
-                shell.publish(self.on_input.clone()(character));
+                let Some(terminal_key) = terminal_key(&key) else {
+                    return Status::Ignored;
+                };
+
+                shell.publish(self.on_input.clone()(KeyInput {
+                    key: terminal_key,
+                    base_key: base_key(&key),
+                    text: None,
+                    numpad: location == KeyLocation::Numpad,
+                    released: true,
+                    shift: modifiers.shift(),
+                    alt: modifiers.alt(),
+                    control: modifiers.control(),
+                }));
                 return Status::Captured;
-                // }
             }
             Event::Mouse(MouseEvent::WheelScrolled { delta }) => {
                 if let Some(p) = cursor.position_in(layout.bounds()) {
@@ -461,12 +1105,12 @@ where
                                 "scrolled lines, top row: {:?},  visible: {:?}, cell len: {:?}",
                                 self.top_displaying_row,
                                 self.visible_rows,
-                                self.cells.len()
+                                self.total_rows()
                             );
                             if y < 0.0 {
-                                if self.cells.len() > self.visible_rows {
+                                if self.total_rows() > self.visible_rows {
                                     if self.top_displaying_row
-                                        < self.cells.len() - self.visible_rows
+                                        < self.total_rows() - self.visible_rows
                                     {
                                         shell.publish(self.on_scroll.clone()(1));
                                         // self.top_displaying_row += 1;
@@ -497,6 +1141,65 @@ where
     }
 }
 
+// the key the terminal has a sequence for, `None` for modifiers on their own and other keys
+// that do not send anything
+fn terminal_key(key: &Key) -> Option<TerminalKey> {
+    let key = match key {
+        Key::Character(character) => TerminalKey::Character(character.to_string()),
+        Key::Named(named) => match named {
+            Named::Space => TerminalKey::Character(" ".to_string()),
+            Named::Enter => TerminalKey::Enter,
+            Named::Tab => TerminalKey::Tab,
+            Named::Backspace => TerminalKey::Backspace,
+            Named::Escape => TerminalKey::Escape,
+            Named::ArrowUp => TerminalKey::Up,
+            Named::ArrowDown => TerminalKey::Down,
+            Named::ArrowLeft => TerminalKey::Left,
+            Named::ArrowRight => TerminalKey::Right,
+            Named::Home => TerminalKey::Home,
+            Named::End => TerminalKey::End,
+            Named::PageUp => TerminalKey::PageUp,
+            Named::PageDown => TerminalKey::PageDown,
+            Named::Insert => TerminalKey::Insert,
+            Named::Delete => TerminalKey::Delete,
+            Named::F1 => TerminalKey::Function(1),
+            Named::F2 => TerminalKey::Function(2),
+            Named::F3 => TerminalKey::Function(3),
+            Named::F4 => TerminalKey::Function(4),
+            Named::F5 => TerminalKey::Function(5),
+            Named::F6 => TerminalKey::Function(6),
+            Named::F7 => TerminalKey::Function(7),
+            Named::F8 => TerminalKey::Function(8),
+            Named::F9 => TerminalKey::Function(9),
+            Named::F10 => TerminalKey::Function(10),
+            Named::F11 => TerminalKey::Function(11),
+            Named::F12 => TerminalKey::Function(12),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+// the character of a key without shift applied
+fn base_key(key: &Key) -> Option<char> {
+    match key {
+        Key::Character(character) => character.chars().next(),
+        Key::Named(Named::Space) => Some(' '),
+        _ => None,
+    }
+}
+
+fn mouse_button(button: cosmic::iced::mouse::Button) -> Option<MouseButton> {
+    match button {
+        cosmic::iced::mouse::Button::Left => Some(MouseButton::Left),
+        cosmic::iced::mouse::Button::Middle => Some(MouseButton::Middle),
+        cosmic::iced::mouse::Button::Right => Some(MouseButton::Right),
+        _ => None,
+    }
+}
+
 pub unsafe fn make_static_str<'a>(key: &'a str) -> &'static str {
     std::mem::transmute::<&'a str, &'static str>(key)
 }
@@ -1,21 +1,23 @@
 use std::{
     io::{Read, Write},
     thread,
+    time::{Duration, Instant},
 };
 
+use cosmic::iced::window::{self, UserAttention};
+use cosmic::iced::{event, time, Event, Size, Subscription};
 use cosmic::{
     widget::{column, text_input, Column},
-    Action, Application, Task,
+    Action, Application, ApplicationExt, Task,
 };
 use vte::Parser;
 
-use crate::runtimes::libvigil::{self, make_io_subscription, Terminal};
+use crate::app::display::{KeyInput, MouseInput};
+use crate::runtimes::libvigil::{self, make_io_subscription, Terminal, TerminalEvent};
 
-pub struct VigilApp<const NUM_ROW: usize, const NUM_COLUMN: usize> {
+pub struct VigilApp {
     core: cosmic::Core,
-    // TODO: better solution for the width of the termianl, as this seems to be not a good solution
-    // if size were to change
-    terminal: Terminal<NUM_ROW, NUM_COLUMN>,
+    terminal: Terminal,
     terminal_buffer: String,
     parser: Parser,
 }
@@ -24,11 +26,38 @@ pub struct VigilApp<const NUM_ROW: usize, const NUM_COLUMN: usize> {
 pub enum VigilMessages {
     WriteBuffer(String),
     StdoutRead(Vec<u8>),
-    StdinInput(char),
+    KeyPress(KeyInput),
     MouseScroll(i8),
+    // the window was opened or resized to this size
+    Resize(Size),
+    Mouse(MouseInput),
+    Paste(String),
+    // the window gained (`true`) or lost focus
+    Focus(bool),
+    // checks whether a synchronized update has to be ended
+    SynchronizedTimeout,
+    // flips the blinking cursor on or off
+    CursorBlink,
+    // sends the input the shell did not take yet
+    FlushInput,
 }
 
-impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM_ROW, NUM_COLUMN> {
+// how long a blinking cursor stays on and off
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+// how often queued input is sent again while the shell is behind
+const INPUT_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+impl VigilApp {
+    // writes the replies to queries and mouse reports to the shell, as if they were typed
+    fn flush_responses(&mut self) {
+        let responses = self.terminal.take_responses();
+        if let Err(err) = self.terminal.write_input(&responses) {
+            println!("could not send responses: {:?}", err);
+        }
+    }
+}
+
+impl Application for VigilApp {
     type Message = VigilMessages;
     type Executor = cosmic::executor::Default;
     type Flags = ();
@@ -61,7 +90,7 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM
             "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! in here the top displaying row is: {}",
             self.terminal.display.top_displaying_row
         );
-        self.terminal.display.clone().into()
+        self.terminal.rendered_display().into()
     }
 
     fn update(&mut self, message: Self::Message) -> cosmic::Task<Action<Self::Message>> {
@@ -70,13 +99,38 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM
             // VigilMessages::StdoutRead(mut read_bytes) => { self.terminal.read_buffer.append(&mut read_bytes); self.terminal.make_display();}
             VigilMessages::StdoutRead(mut buf) => {
                 // let mut buffer = [0u8; 0x10_0000];
-                println!(
-                    "the buffer to a string: {:?}",
-                    String::from_utf8(buf.clone())
-                        .unwrap_or_default()
-                        .replace("\0", "")
-                );
                 self.parser.advance(&mut self.terminal, &buf);
+                self.terminal.expire_synchronized_update(Instant::now());
+
+                self.flush_responses();
+
+                let mut tasks = Vec::new();
+                for event in self.terminal.take_events() {
+                    match event {
+                        TerminalEvent::Bell => {
+                            // TODO: add a visual bell
+                            if let Some(id) = self.core.main_window_id() {
+                                tasks.push(window::request_user_attention(
+                                    id,
+                                    Some(UserAttention::Informational),
+                                ));
+                            }
+                        }
+                        TerminalEvent::TitleChanged => {
+                            // an empty title goes back to the name of the app
+                            let title = match self.terminal.title.as_str() {
+                                "" => "vigil".to_string(),
+                                title => title.to_string(),
+                            };
+                            if let Some(id) = self.core.main_window_id() {
+                                tasks.push(self.set_window_title(title, id));
+                            }
+                        }
+                    }
+                }
+                if !tasks.is_empty() {
+                    return Task::batch(tasks);
+                }
                 // self.terminal.read_buffer.append(&mut buf);
                 // self.terminal.make_display();
                 // let res = self.terminal.update_buffer();
@@ -86,30 +140,14 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM
                 // self.terminal.make_display();
                 println!("after update term")
             }
-            VigilMessages::StdinInput(char) => {
-                println!("got input {:?}", char);
-                // let stream_clone = self.terminal.stdout_stream.clone();
-                // let mut stream = *stream_clone;
-                println!("pre send stdin stream");
-                // self.terminal.display.cells.clear();
-                // self.terminal.display.cells.push(Vec::new());
-                // self.terminal.cursor_x = 0;
-                // self.terminal.cursor_y = 0;
-                let mut buffer = [0];
-                let res = self
-                    .terminal
-                    .stdin_sender
-                    .write(char.encode_utf8(&mut buffer).as_bytes());
-                println!("got res for stdin send: {:?}", res);
-
-                // .pty
-                // .write_list
-                // .append(&mut char.encode_utf8(&mut buffer).as_bytes().to_vec());
-                // println!("file descriptor: {:?}", self.terminal.master_fd);
-                // self.terminal
-                //     .write_pty(char.encode_utf8(&mut buffer).as_bytes());
-                // let result = stream.write(char.encode_utf8(&mut buffer).as_bytes());
-                // println!("got result {:?}", result);
+            VigilMessages::KeyPress(input) => {
+                println!("got input {:?}", input);
+                // the cursor stays on while typing
+                self.terminal.display.cursor_blink_on = true;
+                let bytes = self.terminal.encode_key(&input);
+                if let Err(err) = self.terminal.write_input(&bytes) {
+                    println!("could not send input: {:?}", err);
+                }
             }
             VigilMessages::MouseScroll(direction) => {
                 if direction > 0 {
@@ -118,6 +156,36 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM
                     self.terminal.display.top_displaying_row -= -direction as usize;
                 }
             }
+            VigilMessages::Resize(size) => {
+                // the display fills the window, so the terminal gets the cells that fit in it
+                let (rows, columns) = self.terminal.display.grid_size(size);
+                self.terminal.resize(rows, columns);
+            }
+            VigilMessages::Mouse(input) => {
+                self.terminal.report_mouse(input);
+                self.flush_responses();
+            }
+            VigilMessages::Paste(text) => {
+                let bytes = self.terminal.encode_paste(&text);
+                if let Err(err) = self.terminal.write_input(&bytes) {
+                    println!("could not send paste: {:?}", err);
+                }
+            }
+            VigilMessages::SynchronizedTimeout => {
+                self.terminal.expire_synchronized_update(Instant::now());
+            }
+            VigilMessages::FlushInput => {
+                if let Err(err) = self.terminal.flush_input() {
+                    println!("could not send input: {:?}", err);
+                }
+            }
+            VigilMessages::CursorBlink => {
+                self.terminal.display.cursor_blink_on = !self.terminal.display.cursor_blink_on;
+            }
+            VigilMessages::Focus(focused) => {
+                self.terminal.set_focus(focused);
+                self.flush_responses();
+            }
         }
         println!("hey i got buffer {:?}", self.terminal_buffer);
 
@@ -125,7 +193,42 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Application for VigilApp<NUM
     }
 
     fn subscription(&self) -> cosmic::iced::Subscription<Self::Message> {
-        make_io_subscription(self.terminal.stdout_stream.try_clone().unwrap())
+        let window_events = event::listen_with(|event, _status, _id| match event {
+            Event::Window(window::Event::Focused) => Some(VigilMessages::Focus(true)),
+            Event::Window(window::Event::Unfocused) => Some(VigilMessages::Focus(false)),
+            Event::Window(window::Event::Opened { size, .. }) => Some(VigilMessages::Resize(size)),
+            Event::Window(window::Event::Resized(size)) => Some(VigilMessages::Resize(size)),
+            _ => None,
+        });
+
+        // only ticks while a synchronized update holds back the screen
+        let synchronized_timeout = match self.terminal.synchronized_frame {
+            Some(_) => time::every(libvigil::SYNCHRONIZED_UPDATE_TIMEOUT)
+                .map(|_| VigilMessages::SynchronizedTimeout),
+            None => Subscription::none(),
+        };
+
+        // the cursor only blinks while it is drawn filled
+        let display = &self.terminal.display;
+        let cursor_blink = if display.cursor_blinking && display.focused {
+            time::every(CURSOR_BLINK_INTERVAL).map(|_| VigilMessages::CursorBlink)
+        } else {
+            Subscription::none()
+        };
+
+        let flush_input = if self.terminal.has_queued_input() {
+            time::every(INPUT_RETRY_INTERVAL).map(|_| VigilMessages::FlushInput)
+        } else {
+            Subscription::none()
+        };
+
+        Subscription::batch([
+            make_io_subscription(self.terminal.stdout_stream.try_clone().unwrap()),
+            window_events,
+            synchronized_timeout,
+            cursor_blink,
+            flush_input,
+        ])
         //     // println!("subscription called!");
         //     self.terminal.subscription(self.terminal.pty.file)
         //     // self.terminal.subscription()
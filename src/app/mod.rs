@@ -1,3 +1,4 @@
 
 pub mod main;
 pub mod display;
+pub mod scrollback;
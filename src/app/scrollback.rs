@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::ops::Index;
+
+use crate::app::display::DisplayRow;
+
+// amount of lines kept unless configured otherwise
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+
+// rows that scrolled off the top of the screen, oldest first. once `max_lines` is reached the
+// oldest row is evicted for every new one
+#[derive(Clone)]
+pub struct Scrollback {
+    rows: VecDeque<DisplayRow>,
+    max_lines: usize,
+}
+
+impl Scrollback {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            rows: VecDeque::new(),
+            max_lines,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+
+    // changes the limit, evicting the oldest rows that no longer fit
+    pub fn set_max_lines(&mut self, max_lines: usize) {
+        self.max_lines = max_lines;
+        while self.rows.len() > max_lines {
+            self.rows.pop_front();
+        }
+        self.rows.shrink_to(max_lines);
+    }
+
+    // adds a row as the newest one, returns whether the oldest row had to be evicted for it
+    pub fn push(&mut self, row: DisplayRow) -> bool {
+        if self.max_lines == 0 {
+            return true;
+        }
+
+        let evicted = self.rows.len() == self.max_lines;
+        if evicted {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+
+        evicted
+    }
+
+    // removes the newest row, used to give rows back to the screen when it grows
+    pub fn pop(&mut self) -> Option<DisplayRow> {
+        self.rows.pop_back()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&DisplayRow> {
+        self.rows.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DisplayRow> {
+        self.rows.iter()
+    }
+
+    // the rows starting at `index`, without walking over the ones before it
+    pub fn rows_from(&self, index: usize) -> impl Iterator<Item = &DisplayRow> {
+        self.rows.range(std::cmp::min(index, self.rows.len())..)
+    }
+
+    // removes every row, oldest first
+    pub fn take(&mut self) -> Vec<DisplayRow> {
+        self.rows.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+}
+
+impl Extend<DisplayRow> for Scrollback {
+    fn extend<T: IntoIterator<Item = DisplayRow>>(&mut self, rows: T) {
+        for row in rows {
+            self.push(row);
+        }
+    }
+}
+
+impl Index<usize> for Scrollback {
+    type Output = DisplayRow;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.rows[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::display::DisplayCell;
+
+    fn row(character: char) -> DisplayRow {
+        let mut row = DisplayRow::new(4, DisplayCell::empty());
+        row[0].character = character;
+        row
+    }
+
+    fn first_characters(scrollback: &Scrollback) -> String {
+        scrollback.iter().map(|row| row[0].character).collect()
+    }
+
+    #[test]
+    fn push_evicts_the_oldest_row() {
+        let mut scrollback = Scrollback::new(3);
+
+        assert!(!scrollback.push(row('a')));
+        assert!(!scrollback.push(row('b')));
+        assert!(!scrollback.push(row('c')));
+        assert!(scrollback.push(row('d')));
+
+        assert_eq!(scrollback.len(), 3);
+        assert_eq!(first_characters(&scrollback), "bcd");
+        assert_eq!(scrollback[0][0].character, 'b');
+    }
+
+    #[test]
+    fn zero_lines_keeps_nothing() {
+        let mut scrollback = Scrollback::new(0);
+
+        assert!(scrollback.push(row('a')));
+        assert!(scrollback.is_empty());
+    }
+
+    #[test]
+    fn shrinking_the_limit_evicts_the_oldest_rows() {
+        let mut scrollback = Scrollback::new(5);
+        scrollback.extend("abcde".chars().map(row));
+
+        scrollback.set_max_lines(2);
+        assert_eq!(first_characters(&scrollback), "de");
+        assert_eq!(scrollback.max_lines(), 2);
+    }
+
+    #[test]
+    fn pop_and_rows_from() {
+        let mut scrollback = Scrollback::new(5);
+        scrollback.extend("abcd".chars().map(row));
+
+        assert_eq!(scrollback.pop().map(|row| row[0].character), Some('d'));
+        let rest: String = scrollback
+            .rows_from(1)
+            .map(|row| row[0].character)
+            .collect();
+        assert_eq!(rest, "bc");
+        assert_eq!(scrollback.rows_from(10).count(), 0);
+    }
+}
@@ -7,7 +7,7 @@ mod app;
 mod runtimes;
 
 fn main() {
-    // let mut term: Terminal<80, 80> = Terminal::init(None);
+    // let mut term: Terminal = Terminal::init(None);
     // term.make_display();
     // term.update_buffer();
 
@@ -21,7 +21,7 @@ fn main() {
     //     println!();
     // }
 
-    let _ = cosmic::app::run::<VigilApp<80, 80>>(
+    let _ = cosmic::app::run::<VigilApp>(
         Settings::default()
             .antialiasing(true)
             .client_decorations(false)
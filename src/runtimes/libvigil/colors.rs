@@ -0,0 +1,94 @@
+// color specifications of OSC 4/10/11/12, in the forms XParseColor accepts for rgb values
+
+// parses `rgb:r/g/b` with 1 to 4 hex digits per channel, or `#rgb` with 1 to 4 hex digits per
+// channel all of the same length. named colors are not supported
+pub fn parse(spec: &str) -> Option<(u8, u8, u8)> {
+    if let Some(channels) = spec.strip_prefix("rgb:") {
+        let channels = channels
+            .split('/')
+            .map(scale_channel)
+            .collect::<Option<Vec<_>>>()?;
+
+        return match channels[..] {
+            [r, g, b] => Some((r, g, b)),
+            _ => None,
+        };
+    }
+
+    let digits = spec.strip_prefix('#')?;
+    if digits.is_empty() || digits.len() % 3 != 0 || digits.len() > 12 {
+        return None;
+    }
+    let length = digits.len() / 3;
+    // unlike `rgb:`, `#` values are not scaled, only their most significant byte is used
+    let channel = |index: usize| {
+        let value =
+            u16::from_str_radix(digits.get(index * length..(index + 1) * length)?, 16).ok()?;
+        Some(match length {
+            1 => (value << 4) as u8,
+            _ => (value >> ((length - 2) * 4)) as u8,
+        })
+    };
+
+    Some((channel(0)?, channel(1)?, channel(2)?))
+}
+
+// the reply to a color query, xterm always answers with 4 digits per channel
+pub fn format((r, g, b): (u8, u8, u8)) -> String {
+    format!(
+        "rgb:{:04x}/{:04x}/{:04x}",
+        r as u16 * 0x101,
+        g as u16 * 0x101,
+        b as u16 * 0x101
+    )
+}
+
+// scales a channel of 1 to 4 hex digits to 8 bits
+fn scale_channel(digits: &str) -> Option<u8> {
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    let value = u32::from_str_radix(digits, 16).ok()?;
+    let max = (1 << (digits.len() * 4)) - 1;
+
+    Some(((value * 0xff + max / 2) / max) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_color_specs() {
+        // (spec, expected color)
+        let cases = [
+            ("rgb:ff/80/00", Some((0xff, 0x80, 0x00))),
+            ("rgb:ffff/8080/0000", Some((0xff, 0x80, 0x00))),
+            ("rgb:f/8/0", Some((0xff, 0x88, 0x00))),
+            ("rgb:fff/000/800", Some((0xff, 0x00, 0x80))),
+            ("rgb:1e1e/1e1e/2e2e", Some((0x1e, 0x1e, 0x2e))),
+            ("#f80", Some((0xf0, 0x80, 0x00))),
+            ("#ff8000", Some((0xff, 0x80, 0x00))),
+            ("#fff888000", Some((0xff, 0x88, 0x00))),
+            ("#ffff88880000", Some((0xff, 0x88, 0x00))),
+            ("rgb:ff/80", None),
+            ("rgb:ff/80/00/00", None),
+            ("rgb:fffff/0/0", None),
+            ("rgb:gg/00/00", None),
+            ("#ff80", None),
+            ("#", None),
+            ("red", None),
+            ("?", None),
+        ];
+
+        for (spec, color) in cases {
+            assert_eq!(parse(spec), color, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn formats_colors_with_four_digits() {
+        assert_eq!(format((0x00, 0x80, 0xff)), "rgb:0000/8080/ffff");
+        assert_eq!(parse(&format((0x12, 0x34, 0x56))), Some((0x12, 0x34, 0x56)));
+    }
+}
@@ -0,0 +1,601 @@
+use crate::app::display::{KeyInput, TerminalKey};
+
+use super::Modes;
+
+// flags of the kitty keyboard protocol, pushed by `CSI > flags u`
+pub const DISAMBIGUATE: u8 = 1 << 0;
+pub const REPORT_EVENT_TYPES: u8 = 1 << 1;
+pub const REPORT_ALTERNATE_KEYS: u8 = 1 << 2;
+pub const REPORT_ALL_KEYS: u8 = 1 << 3;
+pub const REPORT_TEXT: u8 = 1 << 4;
+pub const ALL_FLAGS: u8 = 0b11111;
+
+// encodes a key into the bytes sent to the shell. without keyboard flags this is what xterm
+// would send, cursor keys follow the cursor keys mode (DECCKM) and the keypad follows the
+// keypad application mode (DECKPAM)
+pub fn encode(input: &KeyInput, modes: Modes, keyboard_flags: u8) -> Vec<u8> {
+    // xterm's modifier parameter, only sent when there is a modifier
+    let modifier = 1 + input.shift as u8 + input.alt as u8 * 2 + input.control as u8 * 4;
+
+    if keyboard_flags != 0 {
+        if let Some(sequence) = encode_kitty(input, keyboard_flags, modifier) {
+            return sequence;
+        }
+    }
+
+    // only the kitty protocol reports releases
+    if input.released {
+        return Vec::new();
+    }
+
+    if input.numpad && modes.has(Modes::KEYPAD_APPLICATION) && modifier == 1 {
+        if let Some(final_byte) = keypad_final(&input.key) {
+            return vec![0x1b, b'O', final_byte];
+        }
+    }
+
+    match input.key {
+        TerminalKey::Up => cursor_key(b'A', modifier, modes),
+        TerminalKey::Down => cursor_key(b'B', modifier, modes),
+        TerminalKey::Right => cursor_key(b'C', modifier, modes),
+        TerminalKey::Left => cursor_key(b'D', modifier, modes),
+        TerminalKey::Home => cursor_key(b'H', modifier, modes),
+        TerminalKey::End => cursor_key(b'F', modifier, modes),
+        TerminalKey::Insert => tilde_key(2, modifier),
+        TerminalKey::Delete => tilde_key(3, modifier),
+        TerminalKey::PageUp => tilde_key(5, modifier),
+        TerminalKey::PageDown => tilde_key(6, modifier),
+        TerminalKey::Function(number @ 1..=4) => {
+            let final_byte = b"PQRS"[number as usize - 1];
+            match modifier {
+                1 => vec![0x1b, b'O', final_byte],
+                _ => format!("\x1b[1;{}{}", modifier, final_byte as char).into_bytes(),
+            }
+        }
+        TerminalKey::Function(number) => {
+            let code = match number {
+                5 => 15,
+                6 => 17,
+                7 => 18,
+                8 => 19,
+                9 => 20,
+                10 => 21,
+                11 => 23,
+                12 => 24,
+                _ => return Vec::new(),
+            };
+            tilde_key(code, modifier)
+        }
+        TerminalKey::Tab if input.shift => b"\x1b[Z".to_vec(),
+        TerminalKey::Tab => alt_prefixed(b"\t", input.alt),
+        TerminalKey::Enter => alt_prefixed(b"\r", input.alt),
+        TerminalKey::Escape => alt_prefixed(b"\x1b", input.alt),
+        // ctrl+backspace sends the other erase character
+        TerminalKey::Backspace if input.control => alt_prefixed(b"\x08", input.alt),
+        TerminalKey::Backspace => alt_prefixed(b"\x7f", input.alt),
+        TerminalKey::Character(ref character) => {
+            let control = match input.control {
+                true => control_character(character),
+                false => None,
+            };
+            let bytes = match (control, &input.text) {
+                (Some(byte), _) => vec![byte],
+                // the text can hold control characters the layout made up, the character is
+                // used instead then
+                (None, Some(text)) if !text.chars().any(char::is_control) => {
+                    text.as_bytes().to_vec()
+                }
+                (None, _) => character.as_bytes().to_vec(),
+            };
+            alt_prefixed(&bytes, input.alt)
+        }
+    }
+}
+
+// encodes a key with the kitty keyboard protocol, `None` when the key is sent the same way as
+// without it
+fn encode_kitty(input: &KeyInput, flags: u8, modifier: u8) -> Option<Vec<u8>> {
+    if input.released && flags & REPORT_EVENT_TYPES == 0 {
+        return Some(Vec::new());
+    }
+    let all_keys = flags & REPORT_ALL_KEYS != 0;
+    // the modifiers other than shift change what a key means
+    let modified = input.alt || input.control;
+
+    // the number and final byte of the sequence
+    let (code, final_byte) = match input.key {
+        TerminalKey::Character(ref character) => {
+            // keys that only type text keep doing that
+            if !all_keys && !modified && !input.released {
+                return None;
+            }
+            let code = input
+                .base_key
+                .or_else(|| character.chars().next())
+                .map(|character| character.to_lowercase().next().unwrap_or(character))?;
+            (code as u32, 'u')
+        }
+        TerminalKey::Escape => (27, 'u'),
+        // these stay the same so that a shell can still be used after a program that did not
+        // reset the flags, their releases are only reported along with every other key
+        TerminalKey::Enter | TerminalKey::Tab | TerminalKey::Backspace
+            if !all_keys && input.released =>
+        {
+            return Some(Vec::new())
+        }
+        TerminalKey::Enter | TerminalKey::Tab | TerminalKey::Backspace
+            if !all_keys && modifier == 1 =>
+        {
+            return None
+        }
+        TerminalKey::Enter => (13, 'u'),
+        TerminalKey::Tab => (9, 'u'),
+        TerminalKey::Backspace => (127, 'u'),
+        // the other keys only change when they carry more than xterm sends for them
+        _ if modifier == 1 && !input.released => return None,
+        TerminalKey::Up => (1, 'A'),
+        TerminalKey::Down => (1, 'B'),
+        TerminalKey::Right => (1, 'C'),
+        TerminalKey::Left => (1, 'D'),
+        TerminalKey::Home => (1, 'H'),
+        TerminalKey::End => (1, 'F'),
+        TerminalKey::Insert => (2, '~'),
+        TerminalKey::Delete => (3, '~'),
+        TerminalKey::PageUp => (5, '~'),
+        TerminalKey::PageDown => (6, '~'),
+        TerminalKey::Function(1) => (1, 'P'),
+        TerminalKey::Function(2) => (1, 'Q'),
+        // `CSI R` would be a cursor position report
+        TerminalKey::Function(3) => (13, '~'),
+        TerminalKey::Function(4) => (1, 'S'),
+        TerminalKey::Function(number) => {
+            let code = match number {
+                5 => 15,
+                6 => 17,
+                7 => 18,
+                8 => 19,
+                9 => 20,
+                10 => 21,
+                11 => 23,
+                12 => 24,
+                _ => return Some(Vec::new()),
+            };
+            (code, '~')
+        }
+    };
+
+    let mut key = code.to_string();
+    // the shifted key, for shortcuts that are written with it
+    if flags & REPORT_ALTERNATE_KEYS != 0 && input.shift && final_byte == 'u' {
+        if let TerminalKey::Character(ref character) = input.key {
+            let shifted = character.chars().next().map(|character| character as u32);
+            if let Some(shifted) = shifted.filter(|&shifted| shifted != code) {
+                key = format!("{}:{}", key, shifted);
+            }
+        }
+    }
+
+    let mut modifiers = modifier.to_string();
+    if input.released {
+        modifiers.push_str(":3");
+    }
+
+    // control combinations do not produce text, even when the platform reports some
+    let text = match (&input.text, input.released || input.control) {
+        (Some(text), false) if flags & REPORT_TEXT != 0 && all_keys => text
+            .chars()
+            .filter(|character| !character.is_control())
+            .map(|character| (character as u32).to_string())
+            .collect::<Vec<_>>()
+            .join(":"),
+        _ => String::new(),
+    };
+
+    let mut sequence = String::from("\x1b[");
+    if !text.is_empty() {
+        sequence.push_str(&format!("{};{};{}", key, modifiers, text));
+    } else if modifiers != "1" {
+        sequence.push_str(&format!("{};{}", key, modifiers));
+    } else if final_byte == 'u' || final_byte == '~' {
+        sequence.push_str(&key);
+    }
+    sequence.push(final_byte);
+
+    Some(sequence.into_bytes())
+}
+
+// the arrows, home and end send `CSI x`, or `SS3 x` in cursor keys mode, with modifiers they
+// always send `CSI 1 ; modifier x`
+fn cursor_key(final_byte: u8, modifier: u8, modes: Modes) -> Vec<u8> {
+    match (modifier, modes.has(Modes::CURSOR_KEYS)) {
+        (1, true) => vec![0x1b, b'O', final_byte],
+        (1, false) => vec![0x1b, b'[', final_byte],
+        _ => format!("\x1b[1;{}{}", modifier, final_byte as char).into_bytes(),
+    }
+}
+
+fn tilde_key(code: u8, modifier: u8) -> Vec<u8> {
+    match modifier {
+        1 => format!("\x1b[{}~", code).into_bytes(),
+        _ => format!("\x1b[{};{}~", code, modifier).into_bytes(),
+    }
+}
+
+// alt sends an escape before the key
+fn alt_prefixed(bytes: &[u8], alt: bool) -> Vec<u8> {
+    let mut sequence = Vec::with_capacity(bytes.len() + 1);
+    if alt {
+        sequence.push(0x1b);
+    }
+    sequence.extend_from_slice(bytes);
+
+    sequence
+}
+
+// the control character for ctrl and a key, like ctrl+a being 0x01
+fn control_character(character: &str) -> Option<u8> {
+    let mut chars = character.chars();
+    let (Some(character), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+
+    match character {
+        'a'..='z' => Some(character as u8 - b'a' + 1),
+        'A'..='Z' => Some(character as u8 - b'A' + 1),
+        ' ' | '@' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '~' | '6' => Some(0x1e),
+        '_' | '/' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+// the final byte of the `SS3` sequence a keypad key sends in keypad application mode
+fn keypad_final(key: &TerminalKey) -> Option<u8> {
+    match key {
+        TerminalKey::Enter => Some(b'M'),
+        TerminalKey::Character(character) => match character.as_str() {
+            "0" => Some(b'p'),
+            "1" => Some(b'q'),
+            "2" => Some(b'r'),
+            "3" => Some(b's'),
+            "4" => Some(b't'),
+            "5" => Some(b'u'),
+            "6" => Some(b'v'),
+            "7" => Some(b'w'),
+            "8" => Some(b'x'),
+            "9" => Some(b'y'),
+            "*" => Some(b'j'),
+            "+" => Some(b'k'),
+            "," => Some(b'l'),
+            "-" => Some(b'm'),
+            "." => Some(b'n'),
+            "/" => Some(b'o'),
+            "=" => Some(b'X'),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: TerminalKey) -> KeyInput {
+        KeyInput {
+            key,
+            base_key: None,
+            text: None,
+            numpad: false,
+            released: false,
+            shift: false,
+            alt: false,
+            control: false,
+        }
+    }
+
+    fn character(character: &str) -> KeyInput {
+        KeyInput {
+            base_key: character.to_lowercase().chars().next(),
+            text: Some(character.to_string()),
+            ..key(TerminalKey::Character(character.to_string()))
+        }
+    }
+
+    fn shift(input: KeyInput) -> KeyInput {
+        KeyInput {
+            shift: true,
+            ..input
+        }
+    }
+
+    fn alt(input: KeyInput) -> KeyInput {
+        KeyInput { alt: true, ..input }
+    }
+
+    fn control(input: KeyInput) -> KeyInput {
+        KeyInput {
+            control: true,
+            ..input
+        }
+    }
+
+    fn numpad(input: KeyInput) -> KeyInput {
+        KeyInput {
+            numpad: true,
+            ..input
+        }
+    }
+
+    #[test]
+    fn encodes_keys() {
+        use TerminalKey::*;
+
+        let cursor_keys = {
+            let mut modes = Modes::default();
+            modes.set(Modes::CURSOR_KEYS, true);
+            modes
+        };
+        let keypad = {
+            let mut modes = Modes::default();
+            modes.set(Modes::KEYPAD_APPLICATION, true);
+            modes
+        };
+
+        // (description, key, modes, expected bytes)
+        let cases: &[(&str, KeyInput, Modes, &[u8])] = &[
+            ("text", character("a"), Modes::default(), b"a"),
+            (
+                "unicode text",
+                character("é"),
+                Modes::default(),
+                "é".as_bytes(),
+            ),
+            ("enter", key(Enter), Modes::default(), b"\r"),
+            ("tab", key(Tab), Modes::default(), b"\t"),
+            ("shift tab", shift(key(Tab)), Modes::default(), b"\x1b[Z"),
+            ("backspace", key(Backspace), Modes::default(), b"\x7f"),
+            (
+                "ctrl backspace",
+                control(key(Backspace)),
+                Modes::default(),
+                b"\x08",
+            ),
+            ("escape", key(Escape), Modes::default(), b"\x1b"),
+            ("up", key(Up), Modes::default(), b"\x1b[A"),
+            (
+                "down in cursor keys mode",
+                key(Down),
+                cursor_keys,
+                b"\x1bOB",
+            ),
+            (
+                "ctrl right",
+                control(key(Right)),
+                Modes::default(),
+                b"\x1b[1;5C",
+            ),
+            (
+                "shift left in cursor keys mode",
+                shift(key(Left)),
+                cursor_keys,
+                b"\x1b[1;2D",
+            ),
+            ("home", key(Home), Modes::default(), b"\x1b[H"),
+            ("end in cursor keys mode", key(End), cursor_keys, b"\x1bOF"),
+            ("insert", key(Insert), Modes::default(), b"\x1b[2~"),
+            ("delete", key(Delete), Modes::default(), b"\x1b[3~"),
+            ("page up", key(PageUp), Modes::default(), b"\x1b[5~"),
+            (
+                "alt page down",
+                alt(key(PageDown)),
+                Modes::default(),
+                b"\x1b[6;3~",
+            ),
+            ("f1", key(Function(1)), Modes::default(), b"\x1bOP"),
+            (
+                "shift f4",
+                shift(key(Function(4))),
+                Modes::default(),
+                b"\x1b[1;2S",
+            ),
+            ("f5", key(Function(5)), Modes::default(), b"\x1b[15~"),
+            ("f12", key(Function(12)), Modes::default(), b"\x1b[24~"),
+            (
+                "ctrl shift f10",
+                shift(control(key(Function(10)))),
+                Modes::default(),
+                b"\x1b[21;6~",
+            ),
+            ("ctrl a", control(character("a")), Modes::default(), b"\x01"),
+            (
+                "ctrl shift z",
+                control(shift(character("Z"))),
+                Modes::default(),
+                b"\x1a",
+            ),
+            (
+                "ctrl space",
+                control(character(" ")),
+                Modes::default(),
+                b"\x00",
+            ),
+            ("ctrl [", control(character("[")), Modes::default(), b"\x1b"),
+            ("ctrl ?", control(character("?")), Modes::default(), b"\x7f"),
+            ("alt x", alt(character("x")), Modes::default(), b"\x1bx"),
+            (
+                "ctrl alt c",
+                alt(control(character("c"))),
+                Modes::default(),
+                b"\x1b\x03",
+            ),
+            ("alt enter", alt(key(Enter)), Modes::default(), b"\x1b\r"),
+            (
+                "keypad digit",
+                numpad(character("5")),
+                Modes::default(),
+                b"5",
+            ),
+            (
+                "application keypad digit",
+                numpad(character("5")),
+                keypad,
+                b"\x1bOu",
+            ),
+            (
+                "application keypad enter",
+                numpad(key(Enter)),
+                keypad,
+                b"\x1bOM",
+            ),
+            (
+                "application keypad minus",
+                numpad(character("-")),
+                keypad,
+                b"\x1bOm",
+            ),
+            (
+                "application keypad arrows",
+                numpad(key(Up)),
+                keypad,
+                b"\x1b[A",
+            ),
+        ];
+
+        for (description, input, modes, bytes) in cases {
+            assert_eq!(encode(input, *modes, 0), *bytes, "{}", description);
+        }
+    }
+
+    #[test]
+    fn control_text_falls_back_to_the_character() {
+        let input = KeyInput {
+            text: Some("\u{1}".to_string()),
+            ..character("a")
+        };
+
+        assert_eq!(encode(&input, Modes::default(), 0), b"a");
+    }
+
+    fn release(input: KeyInput) -> KeyInput {
+        KeyInput {
+            released: true,
+            text: None,
+            ..input
+        }
+    }
+
+    #[test]
+    fn encodes_kitty_keys() {
+        use TerminalKey::*;
+
+        let disambiguate = DISAMBIGUATE;
+        let events = DISAMBIGUATE | REPORT_EVENT_TYPES;
+        let alternates = DISAMBIGUATE | REPORT_ALTERNATE_KEYS;
+        let all_keys = DISAMBIGUATE | REPORT_ALL_KEYS;
+        let text = REPORT_ALL_KEYS | REPORT_TEXT;
+
+        // (description, key, flags, expected bytes)
+        let cases: &[(&str, KeyInput, u8, &[u8])] = &[
+            ("text stays text", character("a"), disambiguate, b"a"),
+            (
+                "shifted text stays text",
+                shift(character("A")),
+                disambiguate,
+                b"A",
+            ),
+            ("escape", key(Escape), disambiguate, b"\x1b[27u"),
+            (
+                "ctrl i is not tab",
+                control(character("i")),
+                disambiguate,
+                b"\x1b[105;5u",
+            ),
+            ("tab stays tab", key(Tab), disambiguate, b"\t"),
+            ("enter stays enter", key(Enter), disambiguate, b"\r"),
+            (
+                "ctrl enter",
+                control(key(Enter)),
+                disambiguate,
+                b"\x1b[13;5u",
+            ),
+            ("shift tab", shift(key(Tab)), disambiguate, b"\x1b[9;2u"),
+            ("alt a", alt(character("a")), disambiguate, b"\x1b[97;3u"),
+            (
+                "ctrl shift a",
+                control(shift(character("A"))),
+                disambiguate,
+                b"\x1b[97;6u",
+            ),
+            ("arrows stay the same", key(Up), disambiguate, b"\x1b[A"),
+            ("ctrl arrow", control(key(Up)), disambiguate, b"\x1b[1;5A"),
+            ("f3", shift(key(Function(3))), disambiguate, b"\x1b[13;2~"),
+            (
+                "releases need event types",
+                release(character("a")),
+                disambiguate,
+                b"",
+            ),
+            (
+                "text release",
+                release(character("a")),
+                events,
+                b"\x1b[97;1:3u",
+            ),
+            ("arrow release", release(key(Left)), events, b"\x1b[1;1:3D"),
+            ("enter release", release(key(Enter)), events, b""),
+            (
+                "delete release",
+                release(key(Delete)),
+                events,
+                b"\x1b[3;1:3~",
+            ),
+            (
+                "press with event types",
+                control(character("c")),
+                events,
+                b"\x1b[99;5u",
+            ),
+            (
+                "alternate key",
+                control(shift(character("A"))),
+                alternates,
+                b"\x1b[97:65;6u",
+            ),
+            ("all keys text", character("a"), all_keys, b"\x1b[97u"),
+            ("all keys enter", key(Enter), all_keys, b"\x1b[13u"),
+            ("all keys backspace", key(Backspace), all_keys, b"\x1b[127u"),
+            (
+                "all keys enter release",
+                release(key(Enter)),
+                all_keys | events,
+                b"\x1b[13;1:3u",
+            ),
+            (
+                "associated text",
+                shift(character("A")),
+                text,
+                b"\x1b[97;2;65u",
+            ),
+            (
+                "no text for control",
+                control(character("a")),
+                text,
+                b"\x1b[97;5u",
+            ),
+        ];
+
+        for (description, input, flags, bytes) in cases {
+            assert_eq!(
+                String::from_utf8(encode(input, Modes::default(), *flags)).unwrap(),
+                String::from_utf8(bytes.to_vec()).unwrap(),
+                "{}",
+                description
+            );
+        }
+    }
+}
@@ -6,23 +6,30 @@ use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::os::unix::process::CommandExt;
 use std::process::Child;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::task::Waker;
 use std::{thread, u8};
 // use std::os::unix::net::{UnixListener, UnixStream};
-use std::{os::fd::RawFd, process::Command, time::Duration};
+use std::{
+    os::fd::RawFd,
+    process::Command,
+    time::{Duration, Instant},
+};
 
-use cosmic::iced::futures::StreamExt;
+use cosmic::iced::futures::{SinkExt, StreamExt};
 use cosmic::iced::{futures, stream, Subscription};
 use cosmic::Element;
 use lazy_static::lazy_static;
+use nix::errno::Errno;
 use nix::fcntl::FcntlArg::{F_GETFL, F_SETFL};
 use nix::fcntl::{self, OFlag};
 use nix::libc::{
     ioctl, signal, O_NONBLOCK, SIGALRM, SIGCHLD, SIGINT, SIGQUIT, SIGTERM, SIG_DFL, TIOCSCTTY,
+    TIOCSWINSZ,
 };
 use nix::poll::{self, PollFd, PollFlags, PollTimeout};
-use nix::pty::{forkpty, openpty, ForkptyResult};
+use nix::pty::{forkpty, openpty, ForkptyResult, Winsize};
 use nix::sys::socket::MsgFlags;
 use nix::sys::termios::{tcgetattr, tcsetattr, InputFlags, SetArg};
 use nix::unistd::{close, read, setsid, write};
@@ -30,18 +37,61 @@ use polling::{Events, Poller};
 use signal_hook::SigId;
 use tokio::net::{UnixListener as TokioListener, UnixSocket as TokioSocket};
 use tokio::task::spawn_blocking;
-use vte::{Params, Parser, Perform};
+use vte::{Params, ParamsIter, Parser, Perform};
 
-use crate::app::display::{DisplayBundle, DisplayCell, DisplayStyle, TerminalDisplay};
+use crate::app::display::{
+    ColorPalette, CursorShape, DisplayBundle, DisplayCell, DisplayRow, DisplayStyle, KeyInput,
+    MouseInput, NamedColor, TerminalColor, TerminalDisplay,
+};
 use crate::app::main::VigilMessages;
+use crate::app::scrollback::{Scrollback, DEFAULT_SCROLLBACK_LINES};
+
+mod colors;
+mod keys;
+mod modes;
+mod mouse;
+
+pub use modes::Modes;
+
+// size used until the widget is laid out for the first time
+pub const DEFAULT_ROWS: usize = 24;
+pub const DEFAULT_COLUMNS: usize = 80;
+
+// flags pushed beyond this many drop the oldest ones
+const KEYBOARD_STACK_SIZE: usize = 16;
+
+// input waiting for the pty beyond this many bytes stops the relay from taking more
+const PENDING_INPUT_LIMIT: usize = 1 << 16;
+
+// a synchronized update that takes longer than this is ended, so a program that crashed in the
+// middle of one does not freeze the screen
+pub const SYNCHRONIZED_UPDATE_TIMEOUT: Duration = Duration::from_millis(150);
+
+// titles pushed beyond this many drop the oldest ones, like in xterm
+const TITLE_STACK_SIZE: usize = 10;
+
+// markers around pasted text in bracketed paste mode
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+// reported by XTVERSION and the secondary device attributes
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 lazy_static! {
     static ref WRITE_LIST: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
 }
 
-pub struct Terminal<const NUM_ROW: usize, const NUM_COLUMN: usize> {
+pub struct Terminal {
+    // size of the screen, follows the size of the widget
+    pub rows: usize,
+    pub columns: usize,
+    // the pty of the shell, `None` when the terminal is not attached to one
+    pub pty: Option<Pty>,
     pub read_buffer: Vec<u8>,
     pub display: TerminalDisplay<VigilMessages>,
+    // rows that scrolled off the top of the screen, oldest first. the displays handed to the
+    // widget share it, so it is changed through `Rc::make_mut`
+    pub scrollback: Rc<Scrollback>,
     pub cursor_x: usize,
     pub cursor_y: usize,
     pub current_style: Option<DisplayStyle>,
@@ -49,11 +99,138 @@ pub struct Terminal<const NUM_ROW: usize, const NUM_COLUMN: usize> {
     pub stdout_stream: UnixStream,
     pub stdin_sender: UnixStream,
     pub display_start: usize,
-    pub wrapping: bool,
+    // set after printing into the last column, the cursor only wraps once the next character
+    // is printed
+    pub wrap_pending: bool,
+    // the screen that is not being displayed, swapped with `display.cells` when switching
+    // between the primary and alternate screen
+    pub inactive_screen: Vec<DisplayRow>,
+    pub alternate_screen_active: bool,
+    // cursors saved by DECSC, one for the primary and one for the alternate screen
+    pub saved_cursors: [Option<SavedCursor>; 2],
+    // rows that scroll, set by `CSI top ; bottom r`
+    pub scroll_region: std::ops::Range<usize>,
+    // modes set with `CSI h`, like autowrap (DECAWM) and insert mode (IRM)
+    pub modes: Modes,
+    // the last printed character, repeated by `CSI n b`
+    pub last_printed: Option<char>,
+    // whether a tab stop is set at each column
+    pub tab_stops: Vec<bool>,
+    // the G0 and G1 character sets, SO and SI pick which one is used for printing
+    pub charsets: [Charset; 2],
+    pub active_charset: usize,
+    // the flags of the kitty keyboard protocol pushed for the primary and alternate screen, the
+    // last ones are used
+    pub keyboard_flags: [Vec<u8>; 2],
+    // events for the app to handle, taken after every parsed read
+    pub events: Vec<TerminalEvent>,
+    // replies to queries from the shell, the app writes them to the pty after every parsed read
+    pub responses: Vec<u8>,
+    // input the relay thread did not take yet, see `flush_input`
+    pub input_queue: Vec<u8>,
+    // set by OSC 0/1/2, the icon name is only kept for the title stack
+    pub title: String,
+    pub icon_name: String,
+    // titles saved by `CSI 22 t` and restored by `CSI 23 t`, as (icon name, title) with `None`
+    // for the one that was not saved
+    pub title_stack: Vec<(Option<String>, Option<String>)>,
+    // the screen shown while a synchronized update is in progress, and when the update began
+    pub synchronized_frame: Option<(SynchronizedFrame, Instant)>,
     // pub master_fd: RawFd,
 }
 
-impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Terminal<NUM_ROW, NUM_COLUMN> {
+// things that happened in the terminal which the app has to act on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminalEvent {
+    Bell,
+    // the window title changed, it is read from `Terminal::title`
+    TitleChanged,
+}
+
+// character sets that can be designated into G0 and G1 with `ESC ( c` and `ESC ) c`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    Ascii,
+    // the line drawing characters, used by programs like tmux and ncurses for borders
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    pub fn from_designator(designator: u8) -> Option<Self> {
+        match designator {
+            b'B' => Some(Charset::Ascii),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    pub fn map(&self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c,
+            },
+        }
+    }
+}
+
+// cursor state saved by `ESC 7`, `CSI s` and `CSI ? 1049 h`, and restored by `ESC 8`, `CSI u`
+// and `CSI ? 1049 l`
+#[derive(Clone, Copy, Debug)]
+pub struct SavedCursor {
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    pub style: Option<DisplayStyle>,
+    pub origin_mode: bool,
+    pub wrapping: bool,
+    pub wrap_pending: bool,
+    pub charsets: [Charset; 2],
+    pub active_charset: usize,
+}
+
+// the part of the display frozen by a synchronized update, the scrollback, focus, blinking and
+// palette keep following the terminal
+#[derive(Clone)]
+pub struct SynchronizedFrame {
+    pub cells: Vec<DisplayRow>,
+    pub cursor: Option<(usize, usize)>,
+    pub cursor_shape: CursorShape,
+    pub cursor_blinking: bool,
+}
+
+impl Terminal {
     pub fn init(shell: Option<(String, Vec<String>)>) -> Self {
         // IMPORTANT: remove this once done testing
         let default_shell = shell.unwrap_or(
@@ -65,698 +242,3893 @@ impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Terminal<NUM_ROW, NUM_COLUMN
             ),
             // std::env::var("SHELL")
         );
+        let display = TerminalDisplay::new(
+            "Lilex Nerd Font".to_string(),
+            // "uushi".to_string(),
+            16.0,
+            Box::new(VigilMessages::KeyPress),
+            Box::new(VigilMessages::MouseScroll),
+            Box::new(VigilMessages::Mouse),
+            Box::new(VigilMessages::Paste),
+            DEFAULT_ROWS,
+            DEFAULT_COLUMNS,
+        );
+
         // TODO: once removed the default shell for testing,
-        let pty = Pty::new(Some(default_shell));
+        let pty = Pty::new(
+            Some(default_shell),
+            window_size(DEFAULT_ROWS, DEFAULT_COLUMNS, &display),
+        );
         let (stdout_stream, stdin_sender) = pty.read_io();
 
         // let stdout_fd = spawn_pty_with_shell(default_shell);
-        Terminal::<NUM_ROW, NUM_COLUMN> {
+        let mut terminal = Self::new(
+            display,
+            DEFAULT_ROWS,
+            DEFAULT_COLUMNS,
+            stdout_stream,
+            stdin_sender,
+        );
+        terminal.pty = Some(pty);
+
+        terminal
+    }
+
+    pub fn new(
+        mut display: TerminalDisplay<VigilMessages>,
+        rows: usize,
+        columns: usize,
+        stdout_stream: UnixStream,
+        stdin_sender: UnixStream,
+    ) -> Self {
+        // writes that do not fit are queued instead of blocking the app
+        if let Err(err) = stdin_sender.set_nonblocking(true) {
+            println!("could not make the input non blocking: {:?}", err);
+        }
+
+        display.cells = vec![DisplayRow::new(columns, DisplayCell::empty()); rows];
+        display.visible_rows = rows;
+        display.visible_columns = columns;
+
+        Terminal {
+            rows,
+            columns,
+            pty: None,
             read_buffer: Vec::new(),
-            display: TerminalDisplay::new(
-                "Lilex Nerd Font".to_string(),
-                // "uushi".to_string(),
-                16.0,
-                Box::new(VigilMessages::StdinInput),
-                Box::new(VigilMessages::MouseScroll),
-                NUM_ROW,
-            ),
+            display,
+            scrollback: Rc::new(Scrollback::new(DEFAULT_SCROLLBACK_LINES)),
             current_style: None,
             cursor_x: 0,
             cursor_y: 0,
             previous_bundle_index: 0,
             display_start: 0,
-            wrapping: true,
+            wrap_pending: false,
+            inactive_screen: vec![DisplayRow::new(columns, DisplayCell::empty()); rows],
+            alternate_screen_active: false,
+            saved_cursors: [None; 2],
+            scroll_region: 0..rows,
+            modes: Modes::default(),
+            last_printed: None,
+            tab_stops: default_tab_stops(0..columns),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            keyboard_flags: [Vec::new(), Vec::new()],
+            events: Vec::new(),
+            responses: Vec::new(),
+            input_queue: Vec::new(),
+            title: String::new(),
+            icon_name: String::new(),
+            title_stack: Vec::new(),
+            synchronized_frame: None,
             // master_fd: pty.file,
             stdout_stream,
             stdin_sender, // make it of type shell
         }
     }
 
-    pub fn cursor_forward(&mut self, amount: usize) {
-        if self.cursor_x < NUM_COLUMN || !self.wrapping {
+    // moves the cursor after a printed character, the wrap to the next line is delayed until
+    // another character is printed so that the last column can be written to
+    pub fn cursor_forward(&mut self) {
+        if self.cursor_x + 1 < self.columns {
             self.cursor_x += 1;
-        } else {
-            // println!("cells {:?}", self.display.cells);
-            println!("y is {:?}", self.cursor_y);
-            self.cursor_x = 0;
-            self.cursor_y += 1;
-            println!("adding cursor y in forward wrapping");
-            // TODO: make scrollback from the lines that arent visible
-            // self.display.cells.push(Vec::new());
-            // IMPORTANT: might have to add a check if the cursor y s too much then make it
-            // increase the scrollback
+        } else if self.modes.has(Modes::AUTOWRAP) {
+            self.wrap_pending = true;
         }
     }
-}
 
-impl<const NUM_ROW: usize, const NUM_COLUMN: usize> Perform for Terminal<NUM_ROW, NUM_COLUMN> {
-    fn print(&mut self, c: char) {
-        // println!("adding char {:?}", c);
-        let mut cell;
-        if let Some(obj_cell) = self.display.cells.get_mut(self.cursor_y) {
-            cell = obj_cell;
-        } else {
-            if self.display.cells.len() < self.cursor_y + 1 {
-                self.display.cells.resize(self.cursor_y + 1, Vec::new());
-            }
-            println!("using the non exist method {:?}", self.cursor_y);
+    // the column of the next tab stop after the cursor, or the last column if there is none
+    pub fn next_tab_stop(&self) -> usize {
+        (self.cursor_x + 1..self.columns)
+            .find(|&column| self.tab_stops[column])
+            .unwrap_or(self.columns - 1)
+    }
 
-            // self.display.cells[self.cursor_y] = Vec::with_capacity(NUM_COLUMN);
-            cell = &mut self.display.cells[self.cursor_y];
-        }
+    // the column of the previous tab stop before the cursor, or the first column if there is none
+    pub fn previous_tab_stop(&self) -> usize {
+        (0..self.cursor_x)
+            .rev()
+            .find(|&column| self.tab_stops[column])
+            .unwrap_or(0)
+    }
 
-        // FIX: right now, you cannot add a cell at a specific index
-        // big issue if you move cursor left 10 times and want to insert characters there
-        if cell.len() < self.cursor_x + 1 {
-            cell.resize(
-                self.cursor_x + 1,
-                DisplayCell {
-                    character: ' ',
-                    style: None,
-                },
-            );
+    // moves to the next tab stop, the cell it started on is marked as a tab when the cells it
+    // went over are empty so that copying the line can give back the tab
+    pub fn horizontal_tab(&mut self) {
+        let start = self.cursor_x;
+        let stop = self.next_tab_stop();
+        let row = &mut self.display.cells[self.cursor_y];
+        if stop > start && row[start..stop].iter().all(DisplayCell::is_empty) {
+            row[start].tab = true;
         }
-        // println!("cursor x is: {:?}", self.cursor_x);
-        cell[self.cursor_x] = DisplayCell {
-            character: c,
-            style: self.current_style,
-        };
 
-        self.cursor_forward(1);
+        self.cursor_x = stop;
+        self.wrap_pending = false;
     }
 
-    fn execute(&mut self, byte: u8) {
-        match byte {
-            // carriage return
-            13 => {
-                self.cursor_x = 0;
-                self.cursor_y += 1;
-                self.display.cells.push(Vec::with_capacity(NUM_COLUMN));
-            }
-            10 => {
-                // self.cursor_y += 1;
-                self.cursor_forward(1);
+    // the text of the given columns of a row, cells that a tab went over become a single tab
+    // again and trailing blanks are left out
+    pub fn copy_row_text(&self, row: &DisplayRow, columns: std::ops::Range<usize>) -> String {
+        let mut text = String::new();
+        let mut column = columns.start;
+        while column < std::cmp::min(columns.end, row.len()) {
+            let cell = row[column];
+            text.push(if cell.tab { '\t' } else { cell.character });
+            column += 1;
 
-                // TODO: this wont work with scrollback i believe
-                if self.cursor_y >= self.display.cells.len() {
-                    self.display.cells.push(Vec::with_capacity(NUM_COLUMN));
+            if cell.tab {
+                while column < columns.end
+                    && column < row.len()
+                    && !self.tab_stops.get(column).copied().unwrap_or(false)
+                    && row[column].is_empty()
+                {
+                    column += 1;
                 }
             }
-            _ => {}
         }
-        if byte != 00 {
-            println!("[execute] {:02x} which is also {:?}", byte, byte);
-        }
-        // println!("[execute] {:02x}", byte);
-        // println!("[thing]: {:?}", self.display.cells)
-    }
-
-    fn hook(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
-        println!(
-            "[hook] params={:?}, intermediates={:?}, ignore={:?}, char={:?}",
-            params, intermediates, ignore, c
-        );
-    }
 
-    fn put(&mut self, byte: u8) {
-        println!("[put] {:02x}", byte);
+        text.trim_end_matches(' ').to_string()
     }
 
-    fn unhook(&mut self) {
-        println!("[unhook]");
+    // removes the events that happened since the last call
+    pub fn take_events(&mut self) -> Vec<TerminalEvent> {
+        std::mem::take(&mut self.events)
     }
 
-    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
-        println!(
-            "[osc_dispatch] params={:?} bell_terminated={}",
-            params, bell_terminated
-        );
+    pub fn set_title(&mut self, title: String) {
+        if self.title != title {
+            self.title = title;
+            self.events.push(TerminalEvent::TitleChanged);
+        }
     }
 
-    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
-        println!(
-            "[csi_dispatch] params={:#?}, intermediates={:?}, ignore={:?}, char={:?}",
-            params, intermediates, ignore, c
-        );
-        let mut params_iter = params.iter();
-        let mut next_param_or = |default: u16| match params_iter.next() {
-            Some(&[param, ..]) if param != 0 => param,
-            _ => default,
+    // `CSI 22 ; which t` saves and `CSI 23 ; which t` restores the icon name (1), the title (2) or
+    // both (0)
+    pub fn title_stack_operation(&mut self, push: bool, which: u16) {
+        let (icon_name, title) = match which {
+            0 => (true, true),
+            1 => (true, false),
+            2 => (false, true),
+            _ => return,
         };
 
-        match c {
-            'm' => {}
-            'J' => match next_param_or(0) {
-                // clear screen from cursor to end
-                0 => {
-                    println!("clearing screen from cursot to end");
-                    // let mut index = 0;
-                    // for bundle in &mut self.display.cells[self.cursor_y] {
-                    //     if bundle.character_start >= self.cursor_x
-                    //         && bundle.character_end <= self.cursor_x
-                    //     {
-                    //         bundle.character_end = self.cursor_x;
-                    //         bundle
-                    //             .characters
-                    //             .truncate(bundle.character_start - self.cursor_x);
-                    //         index += 2;
-                    //         break;
-                    //     }
-                    //     index += 1;
-                    // }
-                    self.display.cells[self.cursor_y].truncate(self.cursor_x);
-                    self.display.cells.truncate(self.cursor_y + 1);
-                }
-                // clear screen from cursor to beggining
-                1 => {
-                    println!("clearing screen from cursot to beggining");
-                    // let mut index = 0;
-                    // for bundle in &mut self.display.cells[self.cursor_y] {
-                    //     if bundle.character_start >= self.cursor_x
-                    //         && bundle.character_end <= self.cursor_x
-                    //     {
-                    //         bundle.character_start = self.cursor_x;
-                    //         bundle.characters = bundle.characters[self.cursor_x..].to_vec();
-                    //         index += 2;
-                    //         break;
-                    //     }
-                    //     index += 1;
-                    // }
-                    self.display.cells[self.cursor_y] =
-                        self.display.cells[self.cursor_y][self.cursor_x..].to_vec();
-
-                    self.display.cells = self.display.cells[..self.cursor_y + 1].to_vec();
-                }
-                //clear entire screen
-                2 => {
-                    self.display.cells = Vec::with_capacity(NUM_ROW);
-                }
-                // clear everything
-                3 => {
-                    self.display.cells = Vec::with_capacity(NUM_ROW);
-                    self.cursor_x = 0;
-                    self.cursor_y = 0;
-                }
-                _ => unimplemented!(),
-            },
-            'K' => match next_param_or(0) {
-                // clear from cursor to end
-                0 => {
-                    println!("clearing from cursot to end");
-                    // let mut index = 0;
-                    // for bundle in &mut self.display.cells[self.cursor_y] {
-                    //     if bundle.character_start >= self.cursor_x
-                    //         && bundle.character_end <= self.cursor_x
-                    //     {
-                    //         bundle.character_end = self.cursor_x;
-                    //         bundle
-                    //             .characters
-                    //             .truncate(bundle.character_start - self.cursor_x);
-                    //         index += 2;
-                    //         break;
-                    //     }
-                    //     index += 1;
-                    // }
-                    self.display.cells[self.cursor_y].truncate(self.cursor_x);
-                }
-                // clear from cursor to beggining
-                1 => {
-                    println!("clearing from cursot to beggining");
-                    // let mut index = 0;
-                    // for bundle in &mut self.display.cells[self.cursor_y] {
-                    //     if bundle.character_start >= self.cursor_x {
-                    //         if bundle.character_end <= self.cursor_x {
-                    //             bundle.character_start = self.cursor_x;
-                    //             bundle.characters = bundle.characters[self.cursor_x..].to_vec();
-                    //         } else {
-                    //             index += 1;
-                    //             break;
-                    //         }
-                    //     }
-                    //     index += 1;
-                    // }
-                    self.display.cells[self.cursor_y] =
-                        self.display.cells[self.cursor_y][self.cursor_x..].to_vec();
-                }
-                //clear entire line
-                2 => {
-                    self.display.cells[self.cursor_y] = Vec::with_capacity(NUM_COLUMN);
-                }
-                // clear everything
-                3 => {
-                    self.display.cells = Vec::with_capacity(NUM_ROW);
-                }
-                _ => unimplemented!(),
-            },
-            // move cursor forward by n
-            'C' => {
-                let amount = next_param_or(1);
-                println!("requested to move forward by: {:?}", amount);
-                // if (self.cursor_x + amount as usize) > NUM_COLUMN {
-                //     // println!(
-                //     //     "!!!!!!!! its too much: {:?} + {:?} = {:?} which is also < {:?}",
-                //     //     self.cursor_x,
-                //     //     amount,
-                //     //     self.cursor_x + amount as usize,
-                //     //     NUM_COLUMN
-                //     // );
-                //     self.cursor_x = NUM_COLUMN;
-                // } else {
-                //     self.cursor_x += amount as usize;
-                // }
-                self.cursor_x = std::cmp::min(self.cursor_x + amount as usize, NUM_COLUMN);
-            }
-            // move cursor backward by n
-            'D' => {
-                let amount = next_param_or(1);
-                self.cursor_x = std::cmp::max(self.cursor_x - amount as usize, 0);
-                // if self.cursor_x - amount as usize > 0 {
-                //     self.cursor_x -= amount as usize;
-                // } else {
-                //     self.cursor_x = 0;
-                // }
+        if push {
+            if self.title_stack.len() == TITLE_STACK_SIZE {
+                self.title_stack.remove(0);
             }
-            // move cursor up by n
-            'A' => {
-                let amount = next_param_or(1);
-                self.cursor_y = std::cmp::max(self.cursor_y - amount as usize, 0);
-                // if self.cursor_y - amount as usize > 0 {
-                //     self.cursor_y -= amount as usize;
-                // } else {
-                //     self.cursor_y = 0;
-                // }
-            }
-            // move cursor down by n
-            'B' => {
-                let amount = next_param_or(1);
-                self.cursor_y += amount as usize;
-                // if (self.cursor_y + amount as usize) < NUM_ROW {
-                //     self.cursor_y = NUM_ROW;
-                // } else {
-                //     self.cursor_y += amount as usize;
-                // }
+            self.title_stack.push((
+                icon_name.then(|| self.icon_name.clone()),
+                title.then(|| self.title.clone()),
+            ));
+            return;
+        }
+
+        let Some((saved_icon_name, saved_title)) = self.title_stack.pop() else {
+            return;
+        };
+        if let Some(saved_icon_name) = saved_icon_name.filter(|_| icon_name) {
+            self.icon_name = saved_icon_name;
+        }
+        if let Some(saved_title) = saved_title.filter(|_| title) {
+            self.set_title(saved_title);
+        }
+    }
+
+    // OSC 4, pairs of a palette index and a color to set it to, or `?` to report it
+    pub fn palette_colors(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        for pair in params.chunks(2) {
+            let [index, spec] = pair else {
+                return;
+            };
+            let Some(index) = parse_number(index).filter(|index| *index < 256) else {
+                continue;
+            };
+
+            let spec = String::from_utf8_lossy(spec);
+            if spec == "?" {
+                let color = colors::format(self.display.palette.colors[index]);
+                self.respond_osc(&format!("4;{};{}", index, color), bell_terminated);
+            } else if let Some(color) = colors::parse(&spec) {
+                self.display.palette.colors[index] = color;
             }
-            // move cursor to begging of line downward
-            'E' => {
-                let amount = next_param_or(1);
-                self.cursor_y += amount as usize;
-                self.cursor_x = 0;
+        }
+    }
+
+    // OSC 10, 11 and 12 set or report the default foreground, background and cursor color. like
+    // in xterm, every color after the first goes to the next one
+    pub fn dynamic_colors(&mut self, first: usize, params: &[&[u8]], bell_terminated: bool) {
+        for (number, spec) in (first..=12).zip(params) {
+            let spec = String::from_utf8_lossy(spec);
+            // reverse video does not change what the colors are
+            let palette = &mut self.display.palette;
+
+            if spec == "?" {
+                let color = match number {
+                    10 => palette.foreground,
+                    11 => palette.background,
+                    _ => palette.cursor.unwrap_or(palette.foreground),
+                };
+                self.respond_osc(
+                    &format!("{};{}", number, colors::format(color)),
+                    bell_terminated,
+                );
+                continue;
             }
-            // move cursor to begging of line upward
-            'F' => {
-                let amount = next_param_or(1);
-                if self.cursor_y != 0 {
-                    self.cursor_y -= amount as usize;
-                    self.cursor_x = 0;
-                }
+
+            let Some(color) = colors::parse(&spec) else {
+                continue;
+            };
+            match number {
+                10 => palette.foreground = color,
+                11 => palette.background = color,
+                _ => palette.cursor = Some(color),
             }
-            //moves to specific column
-            'G' => {
-                let amount = next_param_or(1);
-                // TODO: might need to make it os that this will wrap
-                // see alacritty implementation on how to do it i suppose
-                self.cursor_x = std::cmp::min(amount as usize, NUM_COLUMN);
-                //if amount as usize > NUM_COLUMN {
-                //self.cursor_x = NUM_COLUMN;
-                //} else {
-                //self.cursor_x = amount as usize;
-                //}
-            }
-            // enable alternate buffer
-            'l' => match next_param_or(0) {
-                7 => {
-                    self.wrapping = false;
-                }
-                _ => {}
-            },
-            'h' => match next_param_or(0) {
-                1049 => {
-                    println!("!!!!!!!!!!!!!!!!!!!!!!!!!!! requested alternate screen buffer");
-                    // panic!("no more");
-                }
-                2004 => {
-                    // TODO: implement bracketed paste when you have clipboard pasting working
-                }
-                7 => {
-                    self.wrapping = true;
-                    // panic!("got 7 restore thingy ");
-                }
-                _ => {}
-            },
-            _ => {} // _ => panic!("csi '{}' dispatch not implemented ", c)
         }
     }
 
-    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
-        println!(
-            "[esc_dispatch] intermediates={:?}, ignore={:?}, byte={:02x}",
-            intermediates, ignore, byte
-        );
-    }
-}
+    // OSC 104, resets the given palette entries or all of them
+    pub fn reset_palette_colors(&mut self, params: &[&[u8]]) {
+        let defaults = ColorPalette::default().colors;
+        if params.iter().all(|param| param.is_empty()) {
+            self.display.palette.colors = defaults;
+            return;
+        }
 
-fn spawn_pty_with_shell(default_shell: String) -> RawFd {
-    unsafe {
-        match forkpty(None, None) {
-            Ok(fork_result) => {
-                let stdout_fd;
-                println!("got forkpty result: {:?}", fork_result);
-                println!("getting a stdout of {:?}", std::io::stdout().as_raw_fd());
-                if let ForkptyResult::Parent { child: _, master } = fork_result {
-                    // primary/master part of pty
-                    println!("and it should be compared to {:?}", master);
-                    stdout_fd = master;
-                } else {
-                    println!("unfortunately it is of child");
-                    // secondary/slave part of pty
-                    Command::new(&default_shell)
-                        .spawn()
-                        .expect("failed to spawn");
-                    // std::thread::sleep(Duration::from_millis(2000));
-                    println!("exiting?");
-                    std::process::exit(0);
-                }
-                stdout_fd.into_raw_fd()
-            }
-            Err(e) => panic!("failed to fork {:?}", e),
+        // entries that are not a valid index are ignored
+        let indexes = params
+            .iter()
+            .filter_map(|param| parse_number(param))
+            .filter(|index| *index < 256);
+        for index in indexes {
+            self.display.palette.colors[index] = defaults[index];
         }
     }
-}
 
-pub struct Pty {
-    pub child: Child,
-    pub file: File,
-    pub signal: UnixStream,
-    pub signal_id: SigId,
-}
+    // replies to an OSC query with the terminator it came with
+    fn respond_osc(&mut self, reply: &str, bell_terminated: bool) {
+        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+        self.respond(&format!("\x1b]{}{}", reply, terminator));
+    }
 
-impl Pty {
-    fn new(default_shell: Option<(String, Vec<String>)>) -> Self {
-        let pty = openpty(None, None).unwrap(); // TODO: make winsize argument not be a none
-        let master_fd = pty.master.as_raw_fd();
-        let slave_fd = pty.slave.as_raw_fd();
+    // queues a reply to be written back to the pty
+    pub fn respond(&mut self, response: &str) {
+        self.responses.extend_from_slice(response.as_bytes());
+    }
 
-        if let Ok(mut termios) = tcgetattr(&pty.master) {
-            termios.input_flags.set(InputFlags::IUTF8, true);
+    // removes the replies queued since the last call
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
 
-            let _ = tcsetattr(&pty.master, SetArg::TCSANOW, &termios);
+    // called when the window gains or loses focus, reported to the shell in focus event mode
+    pub fn set_focus(&mut self, focused: bool) {
+        if self.display.focused == focused {
+            return;
         }
+        self.display.focused = focused;
 
-        let mut builder = if let Some((shell, args)) = default_shell {
-            let mut command = Command::new(shell);
-            command.args(args);
+        if self.modes.has(Modes::FOCUS_EVENTS) {
+            self.respond(if focused { "\x1b[I" } else { "\x1b[O" });
+        }
+    }
 
-            command
-        } else {
-            Command::new(std::env::var("SHELL").expect("Could not find a default shell to use"))
+    // DECSCUSR, odd styles blink and even ones do not, 0 is the default blinking block
+    pub fn set_cursor_style(&mut self, style: u16) {
+        let shape = match style {
+            0..=2 => CursorShape::Block,
+            3 | 4 => CursorShape::Underline,
+            5 | 6 => CursorShape::Bar,
+            _ => return,
         };
 
-        builder.stdin(pty.slave.try_clone().unwrap());
-        builder.stderr(pty.slave.try_clone().unwrap());
-        builder.stdout(pty.slave);
+        self.display.cursor_shape = shape;
+        self.display.cursor_blinking = style == 0 || style % 2 == 1;
+        self.display.cursor_blink_on = true;
+    }
 
-        // TODO: set up shell enviroment based on a config
-        // example: https://github.com/alacritty/alacritty/blob/15f1278d695776860ebcd939d30604b253788278/alacritty_terminal/src/tty/unix.rs#L230
+    // the cursor position the display draws the cursor at, `None` while it is hidden (DECTCEM)
+    pub fn visible_cursor(&self) -> Option<(usize, usize)> {
+        if !self.modes.has(Modes::CURSOR_VISIBLE) {
+            return None;
+        }
 
-        // builder.env("TERM", "dumb");
-        builder.env_remove("XDG_ACTIVATION_TOKEN");
-        builder.env_remove("DESKTOP_STARTUP_ID");
+        Some((self.cursor_x, self.cursor_y))
+    }
 
-        unsafe {
-            builder.pre_exec(move || {
-                // create a new process group
-                setsid().expect("Failed to set session id");
+    // sends bytes to the shell as if they were typed. what the relay thread does not take right
+    // away stays queued for `flush_input`, so the app never waits on a shell that is slower
+    // than the input and nothing is dropped
+    pub fn write_input(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.input_queue.extend_from_slice(bytes);
+        self.flush_input()
+    }
 
-                // TODO: set working directory based on config
-                // if let Some() =  {
-                //
-                // }
+    // sends as much of the queued input as the relay thread takes without blocking
+    pub fn flush_input(&mut self) -> std::io::Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.input_queue.len() {
+                break Ok(());
+            }
+            match self.stdin_sender.write(&self.input_queue[written..]) {
+                Ok(0) => break Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(count) => written += count,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
 
-                set_controlling_terminal(slave_fd.as_raw_fd());
+        match result {
+            Ok(()) => drop(self.input_queue.drain(..written)),
+            // the relay thread is gone, there is nobody left to send the input to
+            Err(_) => self.input_queue.clear(),
+        }
+        result
+    }
 
-                // remove fds as we do not need the manymore
-                let _ = close(slave_fd.into_raw_fd());
-                let _ = close(master_fd.into_raw_fd());
+    pub fn has_queued_input(&self) -> bool {
+        !self.input_queue.is_empty()
+    }
 
-                signal(SIGCHLD, SIG_DFL);
-                signal(SIGINT, SIG_DFL);
-                signal(SIGQUIT, SIG_DFL);
-                signal(SIGTERM, SIG_DFL);
-                signal(SIGALRM, SIG_DFL);
+    // sets or resets a mode by its number, `private` for the DEC private modes
+    pub fn set_mode(&mut self, number: u16, private: bool, enabled: bool) {
+        if let Some(mode) = Modes::from_number(number, private) {
+            self.modes.set(mode, enabled);
 
-                Ok(())
-            });
+            match mode {
+                // the cursor goes to the new home position
+                Modes::ORIGIN => self.move_cursor_to_position(0, 0),
+                Modes::REVERSE_VIDEO => self.display.palette.reverse_video = enabled,
+                Modes::SYNCHRONIZED_OUTPUT => self.synchronize_output(enabled),
+                // a new tracking mode replaces the previous one
+                Modes::MOUSE_NORMAL | Modes::MOUSE_BUTTON_EVENT | Modes::MOUSE_ANY_EVENT => {
+                    if enabled {
+                        self.modes.set(Modes::MOUSE_TRACKING, false);
+                        self.modes.set(mode, true);
+                    }
+                    self.display.mouse_tracking = self.modes.has(Modes::MOUSE_TRACKING);
+                }
+                _ => {}
+            }
+            return;
         }
 
-        let (signal, signal_id) = {
-            let (sender, reciever) = UnixStream::pair().unwrap();
+        match (number, private, enabled) {
+            (47, true, true) => self.enter_alternate_screen(false),
+            (47, true, false) => self.leave_alternate_screen(false),
+            (1047, true, true) => self.enter_alternate_screen(false),
+            (1047, true, false) => self.leave_alternate_screen(true),
+            (1049, true, true) => {
+                self.save_cursor();
+                self.enter_alternate_screen(true);
+            }
+            (1049, true, false) => {
+                self.leave_alternate_screen(false);
+                self.restore_cursor();
+            }
+            _ => {}
+        }
+    }
 
-            let signal_id =
-                signal_hook::low_level::pipe::register(signal_hook::consts::SIGCHLD, sender)
-                    .unwrap();
-            let _ = reciever.set_nonblocking(true);
+    // starts or ends a synchronized update, the screen as it is when the update starts is shown
+    // until it ends
+    fn synchronize_output(&mut self, enabled: bool) {
+        if !enabled {
+            self.synchronized_frame = None;
+        } else if self.synchronized_frame.is_none() {
+            let frame = SynchronizedFrame {
+                cells: self.display.cells.clone(),
+                cursor: self.visible_cursor(),
+                cursor_shape: self.display.cursor_shape,
+                cursor_blinking: self.display.cursor_blinking,
+            };
+            self.synchronized_frame = Some((frame, Instant::now()));
+        }
+    }
 
-            (reciever, signal_id)
-        };
+    // ends a synchronized update that went on for longer than the timeout, returns whether it
+    // did
+    pub fn expire_synchronized_update(&mut self, now: Instant) -> bool {
+        match self.synchronized_frame {
+            Some((_, started)) if now.duration_since(started) >= SYNCHRONIZED_UPDATE_TIMEOUT => {
+                self.modes.set(Modes::SYNCHRONIZED_OUTPUT, false);
+                self.synchronized_frame = None;
+                true
+            }
+            _ => false,
+        }
+    }
 
-        match builder.spawn() {
-            Ok(child) => {
-                // let flags = fcntl::fcntl(master_fd.as_raw_fd(), F_GETFL).unwrap();
-                // println!("got flags: {:?}", flags);
-                // let _ = fcntl::fcntl(
-                //     master_fd.as_raw_fd(),
-                //     F_SETFL(OFlag::from_bits(flags).expect("wow")),
-                // )
-                // .unwrap();
-                // let _ = fcntl::fcntl(
-                //     master_fd.as_raw_fd(),
-                //     F_SETFL(
-                //         OFlag::from_bits( fcntl::fcntl(
-                //             master_fd.as_raw_fd(), F_GETFL
-                //         ).expect("epxected 1") | O_NONBLOCK).expect("expected 2")
-                //     )
-                //
-                // );
+    // the display the widget draws, with the screen and cursor of the frame while a
+    // synchronized update is in progress
+    pub fn rendered_display(&self) -> TerminalDisplay<VigilMessages> {
+        let mut display = self.display.clone();
+        display.scrollback = Rc::clone(&self.scrollback);
+        match &self.synchronized_frame {
+            Some((frame, _)) => {
+                display.cells = frame.cells.clone();
+                display.cursor = frame.cursor;
+                display.cursor_shape = frame.cursor_shape;
+                display.cursor_blinking = frame.cursor_blinking;
+            }
+            None => display.cursor = self.visible_cursor(),
+        }
+        display
+    }
 
-                Pty {
-                    child,
-                    file: unsafe { File::from_raw_fd(pty.master.into_raw_fd()) },
-                    signal,
-                    signal_id,
+    // the bytes sent to the shell for a key press
+    pub fn encode_key(&self, input: &KeyInput) -> Vec<u8> {
+        keys::encode(input, self.modes, self.current_keyboard_flags())
+    }
+
+    // the flags of the kitty keyboard protocol used for the current screen
+    pub fn current_keyboard_flags(&self) -> u8 {
+        let stack = &self.keyboard_flags[self.alternate_screen_active as usize];
+        stack.last().copied().unwrap_or(0)
+    }
+
+    // handles the kitty keyboard protocol sequences, `CSI > flags u` pushes flags, `CSI < n u`
+    // pops them, `CSI = flags ; mode u` changes the current ones and `CSI ? u` reports them
+    pub fn keyboard_protocol(&mut self, intermediate: u8, params: &Params) {
+        let mut params = params.iter().map(|param| param[0]);
+        let mut first = params.next();
+        let second = params.next();
+        let stack = &mut self.keyboard_flags[self.alternate_screen_active as usize];
+
+        match intermediate {
+            b'>' => {
+                // the oldest flags are forgotten when the stack gets too deep
+                if stack.len() == KEYBOARD_STACK_SIZE {
+                    stack.remove(0);
                 }
+                stack.push(first.unwrap_or(0) as u8 & keys::ALL_FLAGS);
             }
-            Err(err) => {
-                panic!("Could not spawn terminal child: {:?}", err)
+            b'<' => {
+                let amount = first.take().filter(|amount| *amount != 0).unwrap_or(1);
+                let remaining = stack.len().saturating_sub(amount as usize);
+                stack.truncate(remaining);
+            }
+            b'=' => {
+                let flags = first.unwrap_or(0) as u8 & keys::ALL_FLAGS;
+                if stack.is_empty() {
+                    stack.push(0);
+                }
+                let current = stack.last_mut().unwrap();
+                match second.unwrap_or(1) {
+                    1 => *current = flags,
+                    2 => *current |= flags,
+                    3 => *current &= !flags,
+                    _ => {}
+                }
+            }
+            b'?' => {
+                let flags = self.current_keyboard_flags();
+                self.respond(&format!("\x1b[?{}u", flags));
             }
+            _ => {}
         }
     }
 
-    fn read_io(mut self) -> (UnixStream, UnixStream) {
-        // sender/reciever for stdout
-        let (mut out_sender, out_reciever) = UnixStream::pair().unwrap();
-        // sender/reciever for stdin
-        let (in_sender, mut in_reciever) = UnixStream::pair().unwrap();
-        println!("update buffer was called");
-        // let _ = self.parser.advance(self, &mut self.read_buffer);
-        // let _: Option<i32> = match read_result {
-        //     Ok(bytes_read) => {
-        //
-        //         None
-        //         // Some(read_buffer[..bytes_read].to_vec())
-        //     },
-        //     _ => None
-        // };
-        // let mut parser = Parser::new();
+    // sends a mouse event to the shell, if the tracking mode wants it
+    pub fn report_mouse(&mut self, input: MouseInput) {
+        if let Some(report) = mouse::encode(input, self.modes) {
+            self.responses.extend(report);
+        }
+    }
 
-        thread::spawn(move || {
-            // let mut buf  = []
-            // futures
-            let poller = Poller::new().unwrap();
+    // the bytes sent to the shell for pasted text. with bracketed paste the text is wrapped in
+    // markers, any end marker inside of it is removed so the text cannot end the paste early and
+    // have the rest run as typed input. without it new lines become carriage returns like they
+    // would be when typed
+    pub fn encode_paste(&self, text: &str) -> Vec<u8> {
+        if !self.modes.has(Modes::BRACKETED_PASTE) {
+            return text.replace("\r\n", "\r").replace('\n', "\r").into_bytes();
+        }
 
-            // use polling here instead of loop
+        // removing a marker can join the text around it into a new one
+        let mut text = text.to_string();
+        while text.contains(PASTE_END) {
+            text = text.replace(PASTE_END, "");
+        }
 
-            let mut polling_interest = polling::Event::readable(0);
-            unsafe {
-                poller
-                    .add_with_mode(
-                        &self.file,
-                        // polling::Event::readable(0),
-                        polling_interest,
-                        polling::PollMode::Level,
-                    )
-                    .unwrap();
-                poller
-                    .add_with_mode(
-                        &in_reciever,
-                        polling::Event::readable(1),
-                        polling::PollMode::Level,
-                    )
-                    .unwrap();
-            }
+        format!("{}{}{}", PASTE_START, text, PASTE_END).into_bytes()
+    }
 
-            let mut events = Events::with_capacity(NonZeroUsize::new(1024).unwrap());
-            let mut buffer = [0];
+    // the state of a mode for DECRQM, `None` when it is not recognized
+    pub fn mode_state(&self, number: u16, private: bool) -> Option<bool> {
+        if let Some(mode) = Modes::from_number(number, private) {
+            return Some(self.modes.has(mode));
+        }
 
-            loop {
-                events.clear();
-                let _ = poller.wait(&mut events, None).unwrap();
+        match (number, private) {
+            (47 | 1047 | 1049, true) => Some(self.alternate_screen_active),
+            _ => None,
+        }
+    }
 
-                for event in events.iter() {
-                    match event.key {
-                        0 => {
-                            if event.readable {
-                                match read_from_fd(self.file.as_raw_fd()) {
-                                    Some(read_bytes) => {
-                                        println!("read bytes {:?}", read_bytes);
-                                        println!(
-                                            "to convert those bytes to string: {:?}",
-                                            String::from_utf8(read_bytes.clone())
-                                                .unwrap_or_default()
-                                                .replace("\0", "")
-                                        );
-                                        out_sender.write(&read_bytes).unwrap();
-                                    }
-                                    None => {
-                                        println!("no more to read");
-                                    }
-                                }
-                                // sender.write_all(&read_bytes).unwrap();
-                                println!("readable!!");
-                            } else {
-                                println!("no reading..");
-                            }
+    // replies to DECRQM with whether the mode is set (1), reset (2) or not recognized (0)
+    pub fn report_mode(&mut self, mode: u16, private: bool) {
+        let state = match self.mode_state(mode, private) {
+            Some(true) => 1,
+            Some(false) => 2,
+            None => 0,
+        };
+        let prefix = if private { "?" } else { "" };
 
-                            if event.writable {
-                                // let mut buffer =readable String::new();
-                                // let _ = in_reciever.read_to_string(&mut buffer);
-                                println!("writable!");
-                                // println!("this writable's readability is: {}", event.readable);
-                                let res = self.file.write(&buffer);
-                                println!("the result for writable: {:?}", res);
-                                // self.write(&buffer);
-                                polling_interest.writable = false;
-                                let _ = poller.modify_with_mode(
-                                    &self.file,
-                                    polling_interest,
-                                    polling::PollMode::Level,
-                                );
-                            }
-                        }
-                        1 => {
-                            println!("write list");
-                            if event.readable {
-                                println!("i see its readable");
-                                // let mut buffer = Vec::new();
-                                let _ = in_reciever.read(&mut buffer).unwrap();
-                                println!("i have obtained: {:?}", buffer);
-
-                                polling_interest.writable = true;
-                                let _ = poller.modify_with_mode(
-                                    &self.file,
-                                    polling_interest,
-                                    polling::PollMode::Level,
-                                );
-                            }
-                        }
-                        _ => {
-                            println!("hey i got some weird key: {:?}", event.key);
-                        } // add read write stuff here, and make the thing that sends to main thread
-                          // to update
-                    }
-                }
+        self.respond(&format!("\x1b[{}{};{}$y", prefix, mode, state));
+    }
 
-                // old
-                // println!("repeating read fd");
-                // match read_from_fd(self.file) {
-                //     Some(read_bytes) => {
-                //         // println!("more messaged to read! {:?}", read_bytes);
-                //         // parser.advance(self, &read_bytes);
-                //         println!("read bytes {:?}", read_bytes);
-                //         sender.write_all(&read_bytes).unwrap();
-                //         // self.read_buffer.append(&mut read_bytes);
-                //     }
-                //     None => {
-                //         // no more data to read
-                //         println!(
-                //             "no more data to read ",
-                //             // String::from_utf8_lossy(&self.read_buffer.clone())
-                //         );
-                //         panic!("no more data to read?");
-                //         // break;
-                //     }
-                // }
-            }
-        });
+    // moves the cursor to an absolute position on the screen, clamped to the screen size
+    pub fn move_cursor_to(&mut self, x: usize, y: usize) {
+        self.cursor_x = std::cmp::min(x, self.columns - 1);
+        self.cursor_y = std::cmp::min(y, self.rows - 1);
+        self.wrap_pending = false;
+    }
 
-        (out_reciever, in_sender)
+    // moves the cursor down a line, scrolling the region when it is on its bottom row
+    pub fn linefeed(&mut self) {
+        if self.cursor_y + 1 == self.scroll_region.end {
+            self.scroll_up(1);
+        } else if self.cursor_y + 1 < self.rows {
+            self.cursor_y += 1;
+        }
     }
 
-    // pub fn write(&self, buffer: &[u8]) -> usize {
-    //     unsafe { write(self.file, buffer).unwrap() }
-    // }
-}
+    // moves the cursor to a position given by the cursor positioning sequences, in origin mode
+    // the row is relative to the scroll region and the cursor stays inside of it
+    pub fn move_cursor_to_position(&mut self, x: usize, y: usize) {
+        if self.modes.has(Modes::ORIGIN) {
+            let region = self.scroll_region.clone();
+            self.move_cursor_to(x, std::cmp::min(region.start + y, region.end - 1));
+        } else {
+            self.move_cursor_to(x, y);
+        }
+    }
 
-pub fn make_io_subscription(mut stream_stdout: UnixStream) -> Subscription<VigilMessages> {
-    Subscription::run_with_id(
-        1,
-        stream::channel(100, move |mut output| async move {
-            spawn_blocking(move || loop {
-                let mut buf = [0; 65536];
-                // let mut stdout = stream_stdout;
-                // if let Err(msg) = stream_stdout.read(&mut buf) {
-                //     println!("needs to wait?");
-                // }
-                let read = stream_stdout.read(&mut buf);
+    // moves the cursor up a line, scrolling the region down when it is on its top row
+    pub fn reverse_index(&mut self) {
+        if self.cursor_y == self.scroll_region.start {
+            self.scroll_down(1);
+        } else if self.cursor_y > 0 {
+            self.cursor_y -= 1;
+        }
+    }
 
-                println!("hey i reead: {:?}", read);
+    // scrolls the contents of the scroll region up, the lines leaving the top of the screen go
+    // into the scrollback when the region spans the whole primary screen
+    pub fn scroll_up(&mut self, amount: usize) {
+        let region = self.scroll_region.clone();
+        let keep_scrollback =
+            region.start == 0 && region.end == self.rows && !self.alternate_screen_active;
 
-                output
-                    .try_send(VigilMessages::StdoutRead(buf.to_vec()))
-                    .unwrap();
+        for _ in 0..std::cmp::min(amount, region.len()) {
+            let row = self.display.cells.remove(region.start);
+            self.display.cells.insert(
+                region.end - 1,
+                DisplayRow::new(self.columns, self.blank_cell()),
+            );
 
-                // println!("got msg");
-            });
-            println!("thread after!!");
+            if !keep_scrollback {
+                continue;
+            }
 
-            loop {
-                cosmic::iced_futures::futures::pending!();
+            // keep following the output unless the user scrolled back, in which case the view
+            // stays on the same rows when the oldest one gets evicted
+            let following_output = self.display.top_displaying_row == self.scrollback.len();
+            let evicted = Rc::make_mut(&mut self.scrollback).push(row);
+            if following_output {
+                self.display.top_displaying_row = self.scrollback.len();
+            } else if evicted {
+                self.display.top_displaying_row = self.display.top_displaying_row.saturating_sub(1);
             }
-        }),
-    )
-}
+        }
+    }
 
-fn set_controlling_terminal(fd: RawFd) {
-    let res = unsafe {
-        // TIOSCTTY changes based on platform and the `ioctl` call is different
-        // based on architecture (32/64). So a generic cast is used to make sure
-        // there are no issues. To allow such a generic cast the clippy warning
-        // is disabled.
-        #[allow(clippy::cast_lossless)]
-        ioctl(fd, TIOCSCTTY as _, 0)
-    };
+    // scrolls the contents of the scroll region down, inserting blank lines at its top
+    pub fn scroll_down(&mut self, amount: usize) {
+        let region = self.scroll_region.clone();
 
-    if res < 0 {
-        panic!("ioctl TIOCSCTTY failed");
+        for _ in 0..std::cmp::min(amount, region.len()) {
+            self.display.cells.remove(region.end - 1);
+            self.display.cells.insert(
+                region.start,
+                DisplayRow::new(self.columns, self.blank_cell()),
+            );
+        }
     }
-}
 
-fn read_from_fd(fd: RawFd) -> Option<Vec<u8>> {
-    let mut read_buffer = [0; 65536]; // 0x10_0000
-    println!("reading from buffer");
-    let read_result = read(fd, &mut read_buffer);
-    println!("read result was: {:?}", read_result);
+    // sets the scroll region from 1 based rows, the bottom row being inclusive
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = std::cmp::min(bottom, self.rows);
 
-    match read_result {
-        Ok(bytes_read) => Some(read_buffer[..bytes_read].to_vec()),
-        _ => None,
+        // a region needs at least two lines
+        if top >= bottom {
+            return;
+        }
+
+        self.scroll_region = top - 1..bottom;
+        self.move_cursor_to_position(0, 0);
     }
-}
 
-impl<'a, Message> From<TerminalDisplay<Message>> for Element<'a, Message>
-where
-    Message: Clone + 'a,
-{
-    fn from(terminal_box: TerminalDisplay<Message>) -> Self {
-        Self::new(terminal_box)
+    // switches to the alternate screen, which has no scrollback and is used by full screen
+    // programs so that the primary screen is left as it was once they exit
+    pub fn enter_alternate_screen(&mut self, clear: bool) {
+        if self.alternate_screen_active {
+            return;
+        }
+
+        std::mem::swap(&mut self.display.cells, &mut self.inactive_screen);
+        self.alternate_screen_active = true;
+        self.display.top_displaying_row = self.scrollback.len();
+
+        if clear {
+            self.erase_rows(0..self.rows);
+        }
+    }
+
+    pub fn leave_alternate_screen(&mut self, clear: bool) {
+        if !self.alternate_screen_active {
+            return;
+        }
+
+        if clear {
+            self.erase_rows(0..self.rows);
+        }
+
+        std::mem::swap(&mut self.display.cells, &mut self.inactive_screen);
+        self.alternate_screen_active = false;
+    }
+
+    // changes how many lines the scrollback keeps, the view moves along with the evicted rows
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        let evicted = self.scrollback.len().saturating_sub(lines);
+        Rc::make_mut(&mut self.scrollback).set_max_lines(lines);
+        self.display.top_displaying_row = self.display.top_displaying_row.saturating_sub(evicted);
+    }
+
+    // resizes both screens, lines that no longer fit above the cursor go into the scrollback
+    // and come back out of it once the screen grows again
+    pub fn resize(&mut self, rows: usize, columns: usize) {
+        let rows = std::cmp::max(rows, 1);
+        let columns = std::cmp::max(columns, 1);
+        if rows == self.rows && columns == self.columns {
+            return;
+        }
+
+        let following_output = self.display.top_displaying_row >= self.scrollback.len();
+
+        // the alternate screen never has a scrollback, the primary one might be the inactive
+        // screen and has its cursor saved instead
+        let (saved_x, saved_y) = self.saved_cursors[0]
+            .map_or((0, self.rows - 1), |saved| (saved.cursor_x, saved.cursor_y));
+
+        // only the primary screen gets its wrapped lines reflowed, programs on the alternate
+        // screen redraw it themselves
+        if columns != self.columns {
+            if self.alternate_screen_active {
+                let (x, y) = reflow_screen(
+                    &mut self.inactive_screen,
+                    Rc::make_mut(&mut self.scrollback),
+                    (saved_x, saved_y),
+                    columns,
+                );
+                if let Some(saved) = &mut self.saved_cursors[0] {
+                    saved.cursor_x = x;
+                    saved.cursor_y = y;
+                }
+            } else {
+                // a pending wrap puts the cursor right after the last printed character
+                let cursor_x = self.cursor_x + self.wrap_pending as usize;
+                let (x, y) = reflow_screen(
+                    &mut self.display.cells,
+                    Rc::make_mut(&mut self.scrollback),
+                    (cursor_x, self.cursor_y),
+                    columns,
+                );
+                self.cursor_x = x;
+                self.cursor_y = y;
+            }
+        }
+
+        let saved_y = self.saved_cursors[0].map_or(saved_y, |saved| saved.cursor_y);
+        if self.alternate_screen_active {
+            let shift = resize_screen(
+                &mut self.inactive_screen,
+                Some(Rc::make_mut(&mut self.scrollback)),
+                saved_y,
+                rows,
+                columns,
+            );
+            if let Some(saved) = &mut self.saved_cursors[0] {
+                saved.cursor_y = saved.cursor_y.saturating_add_signed(shift);
+            }
+            let shift = resize_screen(&mut self.display.cells, None, self.cursor_y, rows, columns);
+            self.cursor_y = self.cursor_y.saturating_add_signed(shift);
+        } else {
+            let shift = resize_screen(
+                &mut self.display.cells,
+                Some(Rc::make_mut(&mut self.scrollback)),
+                self.cursor_y,
+                rows,
+                columns,
+            );
+            self.cursor_y = self.cursor_y.saturating_add_signed(shift);
+            resize_screen(&mut self.inactive_screen, None, saved_y, rows, columns);
+        }
+
+        // new columns get the default tab stops, the existing ones are kept
+        let old_columns = self.tab_stops.len();
+        self.tab_stops.truncate(columns);
+        self.tab_stops
+            .extend(default_tab_stops(old_columns..columns));
+
+        self.rows = rows;
+        self.columns = columns;
+        self.scroll_region = 0..rows;
+        self.move_cursor_to(self.cursor_x, self.cursor_y);
+        for saved in self.saved_cursors.iter_mut().flatten() {
+            saved.cursor_x = std::cmp::min(saved.cursor_x, columns - 1);
+            saved.cursor_y = std::cmp::min(saved.cursor_y, rows - 1);
+        }
+
+        self.display.visible_rows = rows;
+        self.display.visible_columns = columns;
+        if let Some(pty) = &self.pty {
+            if let Err(err) = pty.resize(window_size(rows, columns, &self.display)) {
+                println!("could not resize the pty: {:?}", err);
+            }
+        }
+        if following_output || self.display.top_displaying_row > self.scrollback.len() {
+            self.display.top_displaying_row = self.scrollback.len();
+        }
+    }
+
+    // saves the cursor of the current screen
+    pub fn save_cursor(&mut self) {
+        self.saved_cursors[self.alternate_screen_active as usize] = Some(SavedCursor {
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            style: self.current_style,
+            origin_mode: self.modes.has(Modes::ORIGIN),
+            wrapping: self.modes.has(Modes::AUTOWRAP),
+            wrap_pending: self.wrap_pending,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        });
+    }
+
+    // restores the cursor saved on the current screen, without one the cursor goes home and
+    // everything else is reset
+    pub fn restore_cursor(&mut self) {
+        let saved =
+            self.saved_cursors[self.alternate_screen_active as usize].unwrap_or(SavedCursor {
+                cursor_x: 0,
+                cursor_y: 0,
+                style: None,
+                origin_mode: false,
+                wrapping: true,
+                wrap_pending: false,
+                charsets: [Charset::Ascii; 2],
+                active_charset: 0,
+            });
+
+        // the scroll region can have changed since the cursor was saved, in origin mode the
+        // cursor cannot be outside of it
+        let cursor_y = match saved.origin_mode {
+            true => saved
+                .cursor_y
+                .clamp(self.scroll_region.start, self.scroll_region.end - 1),
+            false => saved.cursor_y,
+        };
+        self.move_cursor_to(saved.cursor_x, cursor_y);
+        self.current_style = saved.style;
+        self.modes.set(Modes::ORIGIN, saved.origin_mode);
+        self.modes.set(Modes::AUTOWRAP, saved.wrapping);
+        self.wrap_pending = saved.wrap_pending;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+    }
+
+    // the cell left behind by erasing, it keeps the current background color
+    pub fn blank_cell(&self) -> DisplayCell {
+        let style = self.current_style.map(|style| DisplayStyle {
+            background: style.background,
+            ..DisplayStyle::none()
+        });
+
+        DisplayCell {
+            character: ' ',
+            style: style.filter(|style| *style != DisplayStyle::none()),
+            tab: false,
+        }
+    }
+
+    // erases the cells of a row in the given column range
+    fn erase_cells(&mut self, row: usize, columns: std::ops::Range<usize>) {
+        let blank = self.blank_cell();
+        let erases_line_end = columns.end == self.columns;
+        let row = &mut self.display.cells[row];
+
+        row[columns].fill(blank);
+        if erases_line_end {
+            row.wrapped = false;
+        }
+    }
+
+    fn erase_rows(&mut self, rows: std::ops::Range<usize>) {
+        for row in rows {
+            self.erase_cells(row, 0..self.columns);
+        }
+    }
+
+    // inserts blank cells at the cursor, the cells pushed past the last column are lost
+    pub fn insert_cells(&mut self, amount: usize) {
+        let amount = std::cmp::min(amount, self.columns - self.cursor_x);
+        let blank = self.blank_cell();
+        let row = &mut self.display.cells[self.cursor_y];
+
+        row.truncate(self.columns - amount);
+        row.splice(
+            self.cursor_x..self.cursor_x,
+            std::iter::repeat(blank).take(amount),
+        );
+        self.wrap_pending = false;
+    }
+
+    // deletes cells at the cursor, shifting the rest of the line left
+    pub fn delete_cells(&mut self, amount: usize) {
+        let amount = std::cmp::min(amount, self.columns - self.cursor_x);
+        let blank = self.blank_cell();
+        let row = &mut self.display.cells[self.cursor_y];
+
+        row.drain(self.cursor_x..self.cursor_x + amount);
+        row.extend(std::iter::repeat(blank).take(amount));
+        self.wrap_pending = false;
+    }
+
+    // inserts blank lines at the cursor, the lines pushed past the bottom of the scroll region
+    // are lost
+    pub fn insert_lines(&mut self, amount: usize) {
+        if !self.scroll_region.contains(&self.cursor_y) {
+            return;
+        }
+
+        let bottom = self.scroll_region.end;
+        for _ in 0..std::cmp::min(amount, bottom - self.cursor_y) {
+            self.display.cells.remove(bottom - 1);
+            self.display.cells.insert(
+                self.cursor_y,
+                DisplayRow::new(self.columns, self.blank_cell()),
+            );
+        }
+        self.move_cursor_to(0, self.cursor_y);
+    }
+
+    // deletes lines at the cursor, blank lines are added at the bottom of the scroll region
+    pub fn delete_lines(&mut self, amount: usize) {
+        if !self.scroll_region.contains(&self.cursor_y) {
+            return;
+        }
+
+        let bottom = self.scroll_region.end;
+        for _ in 0..std::cmp::min(amount, bottom - self.cursor_y) {
+            self.display.cells.remove(self.cursor_y);
+            self.display
+                .cells
+                .insert(bottom - 1, DisplayRow::new(self.columns, self.blank_cell()));
+        }
+        self.move_cursor_to(0, self.cursor_y);
+    }
+
+    // applies a SGR (select graphic rendition) sequence to the current style
+    fn set_graphics_rendition(&mut self, params: &Params) {
+        // an empty sequence (`CSI m`) is the same as a reset
+        if params.is_empty() {
+            self.current_style = None;
+            return;
+        }
+
+        let mut style = self.current_style.unwrap_or(DisplayStyle::none());
+        let mut params_iter = params.iter();
+
+        while let Some(param) = params_iter.next() {
+            match param {
+                [0, ..] => style = DisplayStyle::none(),
+                [1, ..] => style.set(DisplayStyle::BOLD, true),
+                [2, ..] => style.set(DisplayStyle::DIM, true),
+                [3, ..] => style.set(DisplayStyle::ITALIC, true),
+                // underline with a style given as a sub parameter, e.g `4:3` for curly
+                [4, kind, ..] => {
+                    style.set(DisplayStyle::ALL_UNDERLINES, false);
+                    match kind {
+                        0 => {}
+                        2 => style.set(DisplayStyle::DOUBLE_UNDERLINE, true),
+                        3 => style.set(DisplayStyle::CURLY_UNDERLINE, true),
+                        4 => style.set(DisplayStyle::DOTTED_UNDERLINE, true),
+                        5 => style.set(DisplayStyle::DASHED_UNDERLINE, true),
+                        _ => style.set(DisplayStyle::UNDERLINE, true),
+                    }
+                }
+                [4] => {
+                    style.set(DisplayStyle::ALL_UNDERLINES, false);
+                    style.set(DisplayStyle::UNDERLINE, true);
+                }
+                [5, ..] => style.set(DisplayStyle::BLINK, true),
+                [6, ..] => style.set(DisplayStyle::RAPID_BLINK, true),
+                [7, ..] => style.set(DisplayStyle::INVERSE, true),
+                [8, ..] => style.set(DisplayStyle::HIDDEN, true),
+                [9, ..] => style.set(DisplayStyle::STRIKETHROUGH, true),
+                [21, ..] => {
+                    style.set(DisplayStyle::ALL_UNDERLINES, false);
+                    style.set(DisplayStyle::DOUBLE_UNDERLINE, true);
+                }
+                [22, ..] => style.set(DisplayStyle::BOLD | DisplayStyle::DIM, false),
+                [23, ..] => style.set(DisplayStyle::ITALIC, false),
+                [24, ..] => style.set(DisplayStyle::ALL_UNDERLINES, false),
+                [25, ..] => style.set(DisplayStyle::BLINK | DisplayStyle::RAPID_BLINK, false),
+                [27, ..] => style.set(DisplayStyle::INVERSE, false),
+                [28, ..] => style.set(DisplayStyle::HIDDEN, false),
+                [29, ..] => style.set(DisplayStyle::STRIKETHROUGH, false),
+                [code @ 30..=37, ..] => style.foreground = named_color(code - 30),
+                [38, ..] => {
+                    if let Some(color) = parse_extended_color(param, &mut params_iter) {
+                        style.foreground = color;
+                    }
+                }
+                [39, ..] => style.foreground = TerminalColor::Default,
+                [code @ 40..=47, ..] => style.background = named_color(code - 40),
+                [48, ..] => {
+                    if let Some(color) = parse_extended_color(param, &mut params_iter) {
+                        style.background = color;
+                    }
+                }
+                [49, ..] => style.background = TerminalColor::Default,
+                // underline color, still has to be consumed so its arguments arent read as
+                // attributes
+                [58, ..] => {
+                    let _ = parse_extended_color(param, &mut params_iter);
+                }
+                [code @ 90..=97, ..] => style.foreground = named_color(code - 90 + 8),
+                [code @ 100..=107, ..] => style.background = named_color(code - 100 + 8),
+                _ => {}
+            }
+        }
+
+        // keep unstyled cells as `None` so they bundle together with the default style
+        self.current_style = if style == DisplayStyle::none() {
+            None
+        } else {
+            Some(style)
+        };
+    }
+}
+
+// the window size reported to the pty, the pixel size is what the cells take up with the
+// current font
+fn window_size<Message>(
+    rows: usize,
+    columns: usize,
+    display: &TerminalDisplay<Message>,
+) -> Winsize {
+    Winsize {
+        ws_row: rows as u16,
+        ws_col: columns as u16,
+        ws_xpixel: (columns as f32 * display.glyph_size) as u16,
+        ws_ypixel: (rows as f32 * display.line_height) as u16,
+    }
+}
+
+// a decimal number in an OSC parameter
+fn parse_number(param: &[u8]) -> Option<usize> {
+    std::str::from_utf8(param).ok()?.parse().ok()
+}
+
+// a tab stop every 8 columns
+fn default_tab_stops(columns: std::ops::Range<usize>) -> Vec<bool> {
+    columns
+        .map(|column| column % 8 == 0 && column != 0)
+        .collect()
+}
+
+// rewraps the logical lines of the scrollback and screen to a new column count, a logical line
+// being the rows joined by their `wrapped` flag. the screen keeps its row count and the returned
+// cursor is on the same character of the line as the given one
+fn reflow_screen(
+    screen: &mut Vec<DisplayRow>,
+    scrollback: &mut Scrollback,
+    (cursor_x, cursor_y): (usize, usize),
+    columns: usize,
+) -> (usize, usize) {
+    let rows = screen.len();
+    let cursor_row = scrollback.len() + cursor_y;
+
+    let mut reflowed: Vec<DisplayRow> = Vec::new();
+    let mut line: Vec<DisplayCell> = Vec::new();
+    let mut cursor_offset = None;
+    let mut cursor = (0, 0);
+
+    let old_rows = scrollback
+        .take()
+        .into_iter()
+        .chain(screen.drain(..))
+        .collect::<Vec<_>>();
+    let row_count = old_rows.len();
+    for (index, row) in old_rows.into_iter().enumerate() {
+        if index == cursor_row {
+            cursor_offset = Some(line.len() + cursor_x);
+        }
+        line.extend(row.cells);
+
+        // a wrapped last row still has to end its line
+        if row.wrapped && index + 1 != row_count {
+            continue;
+        }
+
+        // trailing empty cells are dropped
+        let mut length = line.len();
+        while length > 0 && line[length - 1].is_empty() {
+            length -= 1;
+        }
+
+        // the cell right after the content keeps the cursor where the next character goes, a
+        // cursor further out stays on the last row of the line instead of padding it with rows
+        // that only hold blanks
+        if let Some(offset) = cursor_offset {
+            if offset <= length {
+                length = std::cmp::max(length, offset + 1);
+                cursor = (offset % columns, reflowed.len() + offset / columns);
+            } else {
+                let line_rows = std::cmp::max(length.div_ceil(columns), 1);
+                cursor = match offset / columns < line_rows {
+                    true => (offset % columns, reflowed.len() + offset / columns),
+                    false => (columns - 1, reflowed.len() + line_rows - 1),
+                };
+            }
+        }
+        line.resize(length, DisplayCell::empty());
+
+        if line.is_empty() {
+            reflowed.push(DisplayRow::new(columns, DisplayCell::empty()));
+        }
+        for chunk in line.chunks(columns) {
+            let mut row = DisplayRow {
+                cells: chunk.to_vec(),
+                wrapped: true,
+            };
+            row.resize(columns, DisplayCell::empty());
+            reflowed.push(row);
+        }
+        if let Some(last) = reflowed.last_mut() {
+            last.wrapped = false;
+        }
+
+        line.clear();
+        cursor_offset = None;
+    }
+
+    // blank rows below the cursor are not worth keeping, the screen gets filled back up below
+    while reflowed.len() > cursor.1 + 1 && reflowed.last().is_some_and(DisplayRow::is_blank) {
+        reflowed.pop();
+    }
+    if reflowed.len() < rows {
+        reflowed.resize(rows, DisplayRow::new(columns, DisplayCell::empty()));
+    }
+
+    // the screen ends up being the last rows, unless that would leave the cursor above it
+    let start = std::cmp::min(reflowed.len() - rows, cursor.1);
+    reflowed.truncate(start + rows);
+    *screen = reflowed.split_off(start);
+    scrollback.extend(reflowed);
+
+    (cursor.0, cursor.1 - start)
+}
+
+// resizes a screen to the given size, trimming the empty rows below the cursor first and moving
+// the ones above it into the scrollback. returns how far the rows moved down
+fn resize_screen(
+    screen: &mut Vec<DisplayRow>,
+    mut scrollback: Option<&mut Scrollback>,
+    cursor_y: usize,
+    rows: usize,
+    columns: usize,
+) -> isize {
+    let mut shift = 0;
+
+    if screen.len() > rows {
+        let below_cursor = screen.len().saturating_sub(cursor_y + 1);
+        let excess = screen.len() - rows;
+        screen.truncate(screen.len() - std::cmp::min(excess, below_cursor));
+
+        let excess = screen.len().saturating_sub(rows);
+        let removed = screen.drain(..excess);
+        match scrollback.as_mut() {
+            Some(scrollback) => scrollback.extend(removed),
+            None => drop(removed),
+        }
+        shift -= excess as isize;
+    } else if let Some(scrollback) = scrollback.as_mut() {
+        while screen.len() < rows {
+            let Some(row) = scrollback.pop() else {
+                break;
+            };
+            screen.insert(0, row);
+            shift += 1;
+        }
+    }
+
+    for row in screen.iter_mut() {
+        row.resize(columns, DisplayCell::empty());
+    }
+    screen.resize(rows, DisplayRow::new(columns, DisplayCell::empty()));
+
+    shift
+}
+
+fn named_color(index: u16) -> TerminalColor {
+    NamedColor::from_index(index as usize)
+        .map(TerminalColor::Named)
+        .unwrap_or_default()
+}
+
+// parses the color of an extended color sgr (38, 48 and 58), either in the colon separated form
+// (`38:5:n`, `38:2::r:g:b`) or the semicolon separated form (`38;5;n`, `38;2;r;g;b`)
+fn parse_extended_color(param: &[u16], params_iter: &mut ParamsIter) -> Option<TerminalColor> {
+    let indexed = |index: u16| u8::try_from(index).ok().map(TerminalColor::Indexed);
+    let rgb = |r: u16, g: u16, b: u16| {
+        Some(TerminalColor::Rgb(
+            u8::try_from(r).ok()?,
+            u8::try_from(g).ok()?,
+            u8::try_from(b).ok()?,
+        ))
+    };
+
+    if param.len() > 1 {
+        return match param[1..] {
+            [5, index, ..] => indexed(index),
+            // the color space id is optional, `38:2::r:g:b` and `38:2:r:g:b` are both valid
+            [2, _, r, g, b, ..] | [2, r, g, b] => rgb(r, g, b),
+            _ => None,
+        };
+    }
+
+    match params_iter.next() {
+        Some([5, ..]) => params_iter.next().and_then(|index| indexed(index[0])),
+        Some([2, ..]) => {
+            let mut component = || params_iter.next().map(|value| value[0]);
+            rgb(component()?, component()?, component()?)
+        }
+        _ => None,
+    }
+}
+
+impl Perform for Terminal {
+    fn print(&mut self, c: char) {
+        // println!("adding char {:?}", c);
+        if self.wrap_pending {
+            self.wrap_pending = false;
+            self.display.cells[self.cursor_y].wrapped = true;
+            self.cursor_x = 0;
+            self.linefeed();
+        }
+
+        if self.modes.has(Modes::INSERT) {
+            self.insert_cells(1);
+        }
+
+        let c = self.charsets[self.active_charset].map(c);
+        self.display.cells[self.cursor_y][self.cursor_x] = DisplayCell {
+            character: c,
+            style: self.current_style,
+            tab: false,
+        };
+        self.last_printed = Some(c);
+
+        self.cursor_forward();
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            // bell
+            0x07 => self.events.push(TerminalEvent::Bell),
+            // backspace
+            0x08 => {
+                self.cursor_x = self.cursor_x.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            // horizontal tab
+            0x09 => self.horizontal_tab(),
+            // line feed, vertical tab and form feed all move down a line
+            0x0a..=0x0c => {
+                self.wrap_pending = false;
+                if self.modes.has(Modes::NEWLINE) {
+                    self.cursor_x = 0;
+                }
+                self.linefeed();
+            }
+            // carriage return
+            0x0d => {
+                self.cursor_x = 0;
+                self.wrap_pending = false;
+            }
+            // shift out, print with G1
+            0x0e => self.active_charset = 1,
+            // shift in, print with G0
+            0x0f => self.active_charset = 0,
+            _ => {}
+        }
+        if byte != 00 {
+            println!("[execute] {:02x} which is also {:?}", byte, byte);
+        }
+        // println!("[execute] {:02x}", byte);
+        // println!("[thing]: {:?}", self.display.cells)
+    }
+
+    fn hook(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        println!(
+            "[hook] params={:?}, intermediates={:?}, ignore={:?}, char={:?}",
+            params, intermediates, ignore, c
+        );
+    }
+
+    fn put(&mut self, byte: u8) {
+        println!("[put] {:02x}", byte);
+    }
+
+    fn unhook(&mut self) {
+        println!("[unhook]");
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        println!(
+            "[osc_dispatch] params={:?} bell_terminated={}",
+            params, bell_terminated
+        );
+        let Some((command, rest)) = params.split_first() else {
+            return;
+        };
+        // the text itself can contain `;`, which split it into more params
+        let text = String::from_utf8_lossy(&rest.join(&b';')).into_owned();
+
+        match *command {
+            b"0" => {
+                self.icon_name = text.clone();
+                self.set_title(text);
+            }
+            b"1" => self.icon_name = text,
+            b"2" => self.set_title(text),
+            b"4" => self.palette_colors(rest, bell_terminated),
+            b"10" => self.dynamic_colors(10, rest, bell_terminated),
+            b"11" => self.dynamic_colors(11, rest, bell_terminated),
+            b"12" => self.dynamic_colors(12, rest, bell_terminated),
+            b"104" => self.reset_palette_colors(rest),
+            b"110" => self.display.palette.foreground = ColorPalette::default().foreground,
+            b"111" => self.display.palette.background = ColorPalette::default().background,
+            b"112" => self.display.palette.cursor = None,
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        println!(
+            "[csi_dispatch] params={:#?}, intermediates={:?}, ignore={:?}, char={:?}",
+            params, intermediates, ignore, c
+        );
+        let mut params_iter = params.iter();
+        let mut next_param_or = |default: u16| match params_iter.next() {
+            Some(&[param, ..]) if param != 0 => param,
+            _ => default,
+        };
+
+        match c {
+            'm' => self.set_graphics_rendition(params),
+            'J' => match next_param_or(0) {
+                // clear screen from cursor to end
+                0 => {
+                    println!("clearing screen from cursot to end");
+                    self.erase_cells(self.cursor_y, self.cursor_x..self.columns);
+                    self.erase_rows(self.cursor_y + 1..self.rows);
+                }
+                // clear screen from cursor to beggining
+                1 => {
+                    println!("clearing screen from cursot to beggining");
+                    self.erase_cells(self.cursor_y, 0..self.cursor_x + 1);
+                    self.erase_rows(0..self.cursor_y);
+                }
+                //clear entire screen
+                2 => {
+                    self.erase_rows(0..self.rows);
+                }
+                // clear the scrollback
+                3 => {
+                    Rc::make_mut(&mut self.scrollback).clear();
+                    self.display.top_displaying_row = 0;
+                }
+                _ => {}
+            },
+            'K' => match next_param_or(0) {
+                // clear from cursor to end
+                0 => {
+                    println!("clearing from cursot to end");
+                    self.erase_cells(self.cursor_y, self.cursor_x..self.columns);
+                }
+                // clear from cursor to beggining
+                1 => {
+                    println!("clearing from cursot to beggining");
+                    self.erase_cells(self.cursor_y, 0..self.cursor_x + 1);
+                }
+                //clear entire line
+                2 => {
+                    self.erase_cells(self.cursor_y, 0..self.columns);
+                }
+                _ => {}
+            },
+            // move cursor forward by n
+            'C' => {
+                let amount = next_param_or(1) as usize;
+                println!("requested to move forward by: {:?}", amount);
+                self.move_cursor_to(self.cursor_x + amount, self.cursor_y);
+            }
+            // move cursor backward by n
+            'D' => {
+                let amount = next_param_or(1) as usize;
+                self.move_cursor_to(self.cursor_x.saturating_sub(amount), self.cursor_y);
+            }
+            // move cursor up by n, stopping at the top of the scroll region
+            'A' => {
+                let amount = next_param_or(1) as usize;
+                let top = if self.cursor_y >= self.scroll_region.start {
+                    self.scroll_region.start
+                } else {
+                    0
+                };
+                let row = std::cmp::max(self.cursor_y.saturating_sub(amount), top);
+                self.move_cursor_to(self.cursor_x, row);
+            }
+            // move cursor down by n, stopping at the bottom of the scroll region
+            'B' => {
+                let amount = next_param_or(1) as usize;
+                let bottom = if self.cursor_y < self.scroll_region.end {
+                    self.scroll_region.end - 1
+                } else {
+                    self.rows - 1
+                };
+                let row = std::cmp::min(self.cursor_y + amount, bottom);
+                self.move_cursor_to(self.cursor_x, row);
+            }
+            // move cursor to begging of line downward
+            'E' => {
+                let amount = next_param_or(1) as usize;
+                self.move_cursor_to(0, self.cursor_y + amount);
+            }
+            // move cursor to begging of line upward
+            'F' => {
+                let amount = next_param_or(1) as usize;
+                self.move_cursor_to(0, self.cursor_y.saturating_sub(amount));
+            }
+            // moves to specific column, the parameters of every absolute move are 1 based
+            'G' | '`' => {
+                let column = next_param_or(1) as usize;
+                self.move_cursor_to(column - 1, self.cursor_y);
+            }
+            // moves to specific row
+            'd' => {
+                let row = next_param_or(1) as usize;
+                self.move_cursor_to_position(self.cursor_x, row - 1);
+            }
+            // moves to specific row and column
+            'H' | 'f' => {
+                let row = next_param_or(1) as usize;
+                let column = next_param_or(1) as usize;
+                self.move_cursor_to_position(column - 1, row - 1);
+            }
+            // insert n blank characters
+            '@' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.insert_cells(amount);
+            }
+            // delete n characters
+            'P' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.delete_cells(amount);
+            }
+            // insert n lines
+            'L' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.insert_lines(amount);
+            }
+            // delete n lines
+            'M' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.delete_lines(amount);
+            }
+            // erase n characters, without moving the rest of the line
+            'X' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                let end = std::cmp::min(self.cursor_x + amount, self.columns);
+                self.erase_cells(self.cursor_y, self.cursor_x..end);
+                self.wrap_pending = false;
+            }
+            // repeat the last printed character n times
+            'b' if intermediates.is_empty() => {
+                // more than a screen of repeats would only overwrite itself
+                let amount = std::cmp::min(next_param_or(1) as usize, self.rows * self.columns);
+                if let Some(character) = self.last_printed {
+                    for _ in 0..amount {
+                        self.print(character);
+                    }
+                }
+            }
+            // window operations, only the title stack is supported
+            't' if intermediates.is_empty() => match next_param_or(0) {
+                22 => self.title_stack_operation(true, next_param_or(0)),
+                23 => self.title_stack_operation(false, next_param_or(0)),
+                _ => {}
+            },
+            // clear tab stops, at the cursor or all of them
+            'g' if intermediates.is_empty() => match next_param_or(0) {
+                0 => self.tab_stops[self.cursor_x] = false,
+                3 => self.tab_stops.fill(false),
+                _ => {}
+            },
+            // forward n tab stops
+            'I' if intermediates.is_empty() => {
+                for _ in 0..next_param_or(1) {
+                    self.horizontal_tab();
+                }
+            }
+            // back n tab stops
+            'Z' if intermediates.is_empty() => {
+                for _ in 0..next_param_or(1) {
+                    self.cursor_x = self.previous_tab_stop();
+                }
+                self.wrap_pending = false;
+            }
+            // device status report
+            'n' => match (intermediates, next_param_or(0)) {
+                // the terminal is working fine
+                ([], 5) => self.respond("\x1b[0n"),
+                // cursor position, relative to the scroll region in origin mode
+                (intermediates @ ([] | [b'?']), 6) => {
+                    let row = match self.modes.has(Modes::ORIGIN) {
+                        true => self.cursor_y.saturating_sub(self.scroll_region.start),
+                        false => self.cursor_y,
+                    };
+                    let prefix = if intermediates.is_empty() { "" } else { "?" };
+                    self.respond(&format!(
+                        "\x1b[{}{};{}R",
+                        prefix,
+                        row + 1,
+                        self.cursor_x + 1
+                    ));
+                }
+                _ => {}
+            },
+            // primary device attributes, a vt220 with ansi colors
+            'c' if intermediates.is_empty() && next_param_or(0) == 0 => {
+                self.respond("\x1b[?62;22c")
+            }
+            // secondary device attributes, the version is reported as major * 10000 +
+            // minor * 100 + patch
+            'c' if intermediates == [b'>'] && next_param_or(0) == 0 => {
+                let version = VERSION.split('.').take(3).fold(0, |version, part| {
+                    version * 100 + part.parse::<u32>().unwrap_or(0)
+                });
+                self.respond(&format!("\x1b[>1;{};0c", version))
+            }
+            // set cursor style (DECSCUSR)
+            'q' if intermediates == [b' '] => self.set_cursor_style(next_param_or(0)),
+            // xtversion
+            'q' if intermediates == [b'>'] && next_param_or(0) == 0 => {
+                self.respond(&format!("\x1bP>|vigil({})\x1b\\", VERSION))
+            }
+            // request mode (DECRQM)
+            'p' if intermediates == [b'$'] => self.report_mode(next_param_or(0), false),
+            'p' if intermediates == [b'?', b'$'] => self.report_mode(next_param_or(0), true),
+            // save and restore the cursor
+            's' if intermediates.is_empty() => self.save_cursor(),
+            'u' if intermediates.is_empty() => self.restore_cursor(),
+            // kitty keyboard protocol
+            'u' if intermediates.len() == 1 => self.keyboard_protocol(intermediates[0], params),
+            // set the scroll region
+            'r' if intermediates.is_empty() => {
+                let top = next_param_or(1) as usize;
+                let bottom = next_param_or(self.rows as u16) as usize;
+                self.set_scroll_region(top, bottom);
+            }
+            // scroll up by n
+            'S' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.wrap_pending = false;
+                self.scroll_up(amount);
+            }
+            // scroll down by n
+            'T' if intermediates.is_empty() => {
+                let amount = next_param_or(1) as usize;
+                self.wrap_pending = false;
+                self.scroll_down(amount);
+            }
+            // set and reset modes, every parameter is a mode
+            'h' | 'l' if matches!(intermediates, [] | [b'?']) => {
+                let private = !intermediates.is_empty();
+                for param in params.iter() {
+                    self.set_mode(param[0], private, c == 'h');
+                }
+            }
+            _ => {} // _ => panic!("csi '{}' dispatch not implemented ", c)
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        println!(
+            "[esc_dispatch] intermediates={:?}, ignore={:?}, byte={:02x}",
+            intermediates, ignore, byte
+        );
+
+        match intermediates {
+            // designate G0
+            [b'('] => {
+                if let Some(charset) = Charset::from_designator(byte) {
+                    self.charsets[0] = charset;
+                }
+                return;
+            }
+            // designate G1
+            [b')'] => {
+                if let Some(charset) = Charset::from_designator(byte) {
+                    self.charsets[1] = charset;
+                }
+                return;
+            }
+            [] => {}
+            _ => return,
+        }
+
+        match byte {
+            // index
+            b'D' => {
+                self.wrap_pending = false;
+                self.linefeed();
+            }
+            // next line
+            b'E' => {
+                self.cursor_x = 0;
+                self.wrap_pending = false;
+                self.linefeed();
+            }
+            // keypad application and numeric mode (DECKPAM and DECKPNM)
+            b'=' => self.modes.set(Modes::KEYPAD_APPLICATION, true),
+            b'>' => self.modes.set(Modes::KEYPAD_APPLICATION, false),
+            // save and restore the cursor (DECSC and DECRC)
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            // set a tab stop at the cursor
+            b'H' => self.tab_stops[self.cursor_x] = true,
+            // reverse index
+            b'M' => {
+                self.wrap_pending = false;
+                self.reverse_index();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_pty_with_shell(default_shell: String) -> RawFd {
+    unsafe {
+        match forkpty(None, None) {
+            Ok(fork_result) => {
+                let stdout_fd;
+                println!("got forkpty result: {:?}", fork_result);
+                println!("getting a stdout of {:?}", std::io::stdout().as_raw_fd());
+                if let ForkptyResult::Parent { child: _, master } = fork_result {
+                    // primary/master part of pty
+                    println!("and it should be compared to {:?}", master);
+                    stdout_fd = master;
+                } else {
+                    println!("unfortunately it is of child");
+                    // secondary/slave part of pty
+                    Command::new(&default_shell)
+                        .spawn()
+                        .expect("failed to spawn");
+                    // std::thread::sleep(Duration::from_millis(2000));
+                    println!("exiting?");
+                    std::process::exit(0);
+                }
+                stdout_fd.into_raw_fd()
+            }
+            Err(e) => panic!("failed to fork {:?}", e),
+        }
+    }
+}
+
+pub struct Pty {
+    pub child: Child,
+    pub file: File,
+    pub signal: UnixStream,
+    pub signal_id: SigId,
+}
+
+impl Pty {
+    fn new(default_shell: Option<(String, Vec<String>)>, window_size: Winsize) -> Self {
+        let pty = openpty(Some(&window_size), None).unwrap();
+        let master_fd = pty.master.as_raw_fd();
+        let slave_fd = pty.slave.as_raw_fd();
+
+        if let Ok(mut termios) = tcgetattr(&pty.master) {
+            termios.input_flags.set(InputFlags::IUTF8, true);
+
+            let _ = tcsetattr(&pty.master, SetArg::TCSANOW, &termios);
+        }
+
+        let mut builder = if let Some((shell, args)) = default_shell {
+            let mut command = Command::new(shell);
+            command.args(args);
+
+            command
+        } else {
+            Command::new(std::env::var("SHELL").expect("Could not find a default shell to use"))
+        };
+
+        builder.stdin(pty.slave.try_clone().unwrap());
+        builder.stderr(pty.slave.try_clone().unwrap());
+        builder.stdout(pty.slave);
+
+        // TODO: set up shell enviroment based on a config
+        // example: https://github.com/alacritty/alacritty/blob/15f1278d695776860ebcd939d30604b253788278/alacritty_terminal/src/tty/unix.rs#L230
+
+        // builder.env("TERM", "dumb");
+        builder.env_remove("XDG_ACTIVATION_TOKEN");
+        builder.env_remove("DESKTOP_STARTUP_ID");
+
+        unsafe {
+            builder.pre_exec(move || {
+                // create a new process group
+                setsid().expect("Failed to set session id");
+
+                // TODO: set working directory based on config
+                // if let Some() =  {
+                //
+                // }
+
+                set_controlling_terminal(slave_fd.as_raw_fd());
+
+                // remove fds as we do not need the manymore
+                let _ = close(slave_fd.into_raw_fd());
+                let _ = close(master_fd.into_raw_fd());
+
+                signal(SIGCHLD, SIG_DFL);
+                signal(SIGINT, SIG_DFL);
+                signal(SIGQUIT, SIG_DFL);
+                signal(SIGTERM, SIG_DFL);
+                signal(SIGALRM, SIG_DFL);
+
+                Ok(())
+            });
+        }
+
+        let (signal, signal_id) = {
+            let (sender, reciever) = UnixStream::pair().unwrap();
+
+            let signal_id =
+                signal_hook::low_level::pipe::register(signal_hook::consts::SIGCHLD, sender)
+                    .unwrap();
+            let _ = reciever.set_nonblocking(true);
+
+            (reciever, signal_id)
+        };
+
+        match builder.spawn() {
+            Ok(child) => {
+                // let flags = fcntl::fcntl(master_fd.as_raw_fd(), F_GETFL).unwrap();
+                // println!("got flags: {:?}", flags);
+                // let _ = fcntl::fcntl(
+                //     master_fd.as_raw_fd(),
+                //     F_SETFL(OFlag::from_bits(flags).expect("wow")),
+                // )
+                // .unwrap();
+                // let _ = fcntl::fcntl(
+                //     master_fd.as_raw_fd(),
+                //     F_SETFL(
+                //         OFlag::from_bits( fcntl::fcntl(
+                //             master_fd.as_raw_fd(), F_GETFL
+                //         ).expect("epxected 1") | O_NONBLOCK).expect("expected 2")
+                //     )
+                //
+                // );
+
+                Pty {
+                    child,
+                    file: unsafe { File::from_raw_fd(pty.master.into_raw_fd()) },
+                    signal,
+                    signal_id,
+                }
+            }
+            Err(err) => {
+                panic!("Could not spawn terminal child: {:?}", err)
+            }
+        }
+    }
+
+    // sets the window size of the pty, the kernel then sends a SIGWINCH to the foreground
+    // process group of the shell
+    pub fn resize(&self, window_size: Winsize) -> nix::Result<()> {
+        set_window_size(self.file.as_raw_fd(), &window_size)
+    }
+
+    fn read_io(&self) -> (UnixStream, UnixStream) {
+        // sender/reciever for stdout
+        let (mut out_sender, out_reciever) = UnixStream::pair().unwrap();
+        // sender/reciever for stdin
+        let (in_sender, mut in_reciever) = UnixStream::pair().unwrap();
+        println!("update buffer was called");
+        // let _ = self.parser.advance(self, &mut self.read_buffer);
+        // let _: Option<i32> = match read_result {
+        //     Ok(bytes_read) => {
+        //
+        //         None
+        //         // Some(read_buffer[..bytes_read].to_vec())
+        //     },
+        //     _ => None
+        // };
+        // let mut parser = Parser::new();
+
+        let mut file = self.file.try_clone().unwrap();
+
+        thread::spawn(move || {
+            // let mut buf  = []
+            // futures
+            let poller = Poller::new().unwrap();
+
+            // use polling here instead of loop
+
+            let mut polling_interest = polling::Event::readable(0);
+            unsafe {
+                poller
+                    .add_with_mode(
+                        &file,
+                        // polling::Event::readable(0),
+                        polling_interest,
+                        polling::PollMode::Level,
+                    )
+                    .unwrap();
+                poller
+                    .add_with_mode(
+                        &in_reciever,
+                        polling::Event::readable(1),
+                        polling::PollMode::Level,
+                    )
+                    .unwrap();
+            }
+
+            let mut events = Events::with_capacity(NonZeroUsize::new(1024).unwrap());
+            // input from the app that the shell did not take yet
+            let mut pending: Vec<u8> = Vec::new();
+            let mut input_interest = polling::Event::readable(1);
+            let mut input_closed = false;
+            let mut buffer = [0; 4096];
+
+            // writes only go as far as the pty accepts, the rest stays pending
+            let _ = set_nonblocking(file.as_raw_fd());
+
+            loop {
+                events.clear();
+                let _ = poller.wait(&mut events, None).unwrap();
+
+                for event in events.iter() {
+                    match event.key {
+                        0 => {
+                            if event.readable {
+                                match read_from_fd(file.as_raw_fd()) {
+                                    Some(read_bytes) => {
+                                        println!("read bytes {:?}", read_bytes);
+                                        println!(
+                                            "to convert those bytes to string: {:?}",
+                                            String::from_utf8(read_bytes.clone())
+                                                .unwrap_or_default()
+                                                .replace("\0", "")
+                                        );
+                                        if let Err(err) = out_sender.write_all(&read_bytes) {
+                                            println!("could not send output: {:?}", err);
+                                        }
+                                    }
+                                    None => {
+                                        println!("no more to read");
+                                    }
+                                }
+                                // sender.write_all(&read_bytes).unwrap();
+                                println!("readable!!");
+                            } else {
+                                println!("no reading..");
+                            }
+
+                            if event.writable {
+                                if let Err(err) = write_pending(file.as_raw_fd(), &mut pending) {
+                                    println!("could not write to the pty: {:?}", err);
+                                    pending.clear();
+                                }
+                            }
+                        }
+                        1 => {
+                            if event.readable {
+                                match in_reciever.read(&mut buffer) {
+                                    Ok(0) => {
+                                        println!("input closed");
+                                        input_closed = true;
+                                    }
+                                    Ok(read) => pending.extend_from_slice(&buffer[..read]),
+                                    Err(err) => println!("could not read input: {:?}", err),
+                                }
+                            }
+                        }
+                        _ => {
+                            println!("hey i got some weird key: {:?}", event.key);
+                        } // add read write stuff here, and make the thing that sends to main thread
+                          // to update
+                    }
+                }
+
+                // only wait for the pty to be writable when there is something to write, and stop
+                // taking input when too much is pending, the app keeps the rest queued meanwhile
+                polling_interest.writable = !pending.is_empty();
+                input_interest.readable = !input_closed && pending.len() < PENDING_INPUT_LIMIT;
+                let _ = poller.modify_with_mode(&file, polling_interest, polling::PollMode::Level);
+                let _ =
+                    poller.modify_with_mode(&in_reciever, input_interest, polling::PollMode::Level);
+
+                // old
+                // println!("repeating read fd");
+                // match read_from_fd(file) {
+                //     Some(read_bytes) => {
+                //         // println!("more messaged to read! {:?}", read_bytes);
+                //         // parser.advance(self, &read_bytes);
+                //         println!("read bytes {:?}", read_bytes);
+                //         sender.write_all(&read_bytes).unwrap();
+                //         // self.read_buffer.append(&mut read_bytes);
+                //     }
+                //     None => {
+                //         // no more data to read
+                //         println!(
+                //             "no more data to read ",
+                //             // String::from_utf8_lossy(&self.read_buffer.clone())
+                //         );
+                //         panic!("no more data to read?");
+                //         // break;
+                //     }
+                // }
+            }
+        });
+
+        (out_reciever, in_sender)
+    }
+
+    // pub fn write(&self, buffer: &[u8]) -> usize {
+    //     unsafe { write(self.file, buffer).unwrap() }
+    // }
+}
+
+pub fn make_io_subscription(mut stream_stdout: UnixStream) -> Subscription<VigilMessages> {
+    Subscription::run_with_id(
+        1,
+        stream::channel(100, move |mut output| async move {
+            spawn_blocking(move || loop {
+                let mut buf = [0; 65536];
+                // let mut stdout = stream_stdout;
+                // if let Err(msg) = stream_stdout.read(&mut buf) {
+                //     println!("needs to wait?");
+                // }
+                // the relay closes its end once the shell exits
+                let read = match stream_stdout.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => read,
+                };
+
+                // waits while the app is behind, so the relay thread stops reading from the pty
+                // instead of the channel overflowing
+                let message = VigilMessages::StdoutRead(buf[..read].to_vec());
+                if futures::executor::block_on(output.send(message)).is_err() {
+                    println!("the app stopped listening to the output");
+                    break;
+                }
+
+                // println!("got msg");
+            });
+            println!("thread after!!");
+
+            loop {
+                cosmic::iced_futures::futures::pending!();
+            }
+        }),
+    )
+}
+
+// writes as much of the pending input as the fd takes without blocking and drops what was
+// written, a full fd is not an error
+fn write_pending(fd: RawFd, pending: &mut Vec<u8>) -> nix::Result<()> {
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    let mut written = 0;
+
+    while written < pending.len() {
+        match write(fd, &pending[written..]) {
+            Ok(0) => break,
+            Ok(count) => written += count,
+            Err(Errno::EINTR) => continue,
+            Err(Errno::EAGAIN) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    pending.drain(..written);
+
+    Ok(())
+}
+
+fn set_nonblocking(fd: RawFd) -> nix::Result<()> {
+    let flags = OFlag::from_bits_truncate(fcntl::fcntl(fd, F_GETFL)?);
+    fcntl::fcntl(fd, F_SETFL(flags | OFlag::O_NONBLOCK))?;
+
+    Ok(())
+}
+
+fn set_controlling_terminal(fd: RawFd) {
+    let res = unsafe {
+        // TIOSCTTY changes based on platform and the `ioctl` call is different
+        // based on architecture (32/64). So a generic cast is used to make sure
+        // there are no issues. To allow such a generic cast the clippy warning
+        // is disabled.
+        #[allow(clippy::cast_lossless)]
+        ioctl(fd, TIOCSCTTY as _, 0)
+    };
+
+    if res < 0 {
+        panic!("ioctl TIOCSCTTY failed");
+    }
+}
+
+fn set_window_size(fd: RawFd, window_size: &Winsize) -> nix::Result<()> {
+    let res = unsafe { ioctl(fd, TIOCSWINSZ, window_size as *const Winsize) };
+
+    Errno::result(res).map(drop)
+}
+
+fn read_from_fd(fd: RawFd) -> Option<Vec<u8>> {
+    let mut read_buffer = [0; 65536]; // 0x10_0000
+    println!("reading from buffer");
+    let read_result = read(fd, &mut read_buffer);
+    println!("read result was: {:?}", read_result);
+
+    match read_result {
+        Ok(bytes_read) => Some(read_buffer[..bytes_read].to_vec()),
+        _ => None,
+    }
+}
+
+impl<'a, Message> From<TerminalDisplay<Message>> for Element<'a, Message>
+where
+    Message: Clone + 'a,
+{
+    fn from(terminal_box: TerminalDisplay<Message>) -> Self {
+        Self::new(terminal_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use cosmic::iced_wgpu::graphics::text::cosmic_text::fontdb::Source;
+
+    use super::*;
+    use crate::app::display::{MouseAction, MouseButton, TerminalKey};
+
+    // a terminal that is not attached to a shell or a font, only used to drive the parser
+    fn headless_terminal() -> Terminal {
+        let (stdout_stream, stdin_sender) = UnixStream::pair().unwrap();
+        let display = TerminalDisplay {
+            cells: Vec::new(),
+            scrollback: Rc::new(Scrollback::new(0)),
+            glyph_size: 8.0,
+            font: String::new(),
+            line_height: 16.0,
+            font_source: Source::Binary(Arc::new(Vec::<u8>::new())),
+            font_index: 0,
+            on_input: Rc::new(Box::new(VigilMessages::KeyPress)),
+            top_displaying_row: 0,
+            visible_rows: 24,
+            visible_columns: 80,
+            on_scroll: Rc::new(Box::new(VigilMessages::MouseScroll)),
+            on_mouse: Rc::new(Box::new(VigilMessages::Mouse)),
+            on_paste: Rc::new(Box::new(VigilMessages::Paste)),
+            mouse_tracking: false,
+            cursor: None,
+            focused: true,
+            cursor_shape: CursorShape::Block,
+            cursor_blinking: true,
+            cursor_blink_on: true,
+            palette: ColorPalette::default(),
+        };
+
+        Terminal::new(display, 24, 80, stdout_stream, stdin_sender)
+    }
+
+    fn feed(terminal: &mut Terminal, input: &str) {
+        let mut parser = Parser::new();
+        parser.advance(terminal, input.as_bytes());
+    }
+
+    fn row_text(row: &[DisplayCell]) -> String {
+        String::from_iter(row.iter().map(|cell| cell.character))
+            .trim_end()
+            .to_string()
+    }
+
+    fn style_at(terminal: &Terminal, x: usize, y: usize) -> DisplayStyle {
+        terminal.display.cells[y][x]
+            .style
+            .unwrap_or(DisplayStyle::none())
+    }
+
+    #[test]
+    fn sgr_attributes_apply_to_printed_cells() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "a\x1b[1mb\x1b[2;3mc\x1b[5;7;8;9md");
+
+        assert_eq!(terminal.display.cells[0][0].style, None);
+        assert!(style_at(&terminal, 1, 0).has(DisplayStyle::BOLD));
+
+        let style = style_at(&terminal, 2, 0);
+        for attribute in [DisplayStyle::BOLD, DisplayStyle::DIM, DisplayStyle::ITALIC] {
+            assert!(style.has(attribute));
+        }
+
+        let style = style_at(&terminal, 3, 0);
+        for attribute in [
+            DisplayStyle::BLINK,
+            DisplayStyle::INVERSE,
+            DisplayStyle::HIDDEN,
+            DisplayStyle::STRIKETHROUGH,
+        ] {
+            assert!(style.has(attribute));
+        }
+    }
+
+    #[test]
+    fn sgr_reset_clears_style() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;4;31ma\x1b[0mb\x1b[1mc\x1b[md");
+
+        assert!(terminal.display.cells[0][0].style.is_some());
+        assert_eq!(terminal.display.cells[0][1].style, None);
+        assert!(style_at(&terminal, 2, 0).has(DisplayStyle::BOLD));
+        assert_eq!(terminal.display.cells[0][3].style, None);
+        assert_eq!(terminal.current_style, None);
+    }
+
+    #[test]
+    fn sgr_off_codes_remove_attributes() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;2;3;4;5;7;8;9ma");
+        feed(
+            &mut terminal,
+            "\x1b[22mb\x1b[23mc\x1b[24md\x1b[25me\x1b[27mf\x1b[28mg\x1b[29mh",
+        );
+
+        assert!(!style_at(&terminal, 1, 0).has(DisplayStyle::BOLD | DisplayStyle::DIM));
+        assert!(!style_at(&terminal, 2, 0).has(DisplayStyle::ITALIC));
+        assert!(!style_at(&terminal, 3, 0).has(DisplayStyle::ALL_UNDERLINES));
+        assert!(!style_at(&terminal, 4, 0).has(DisplayStyle::BLINK));
+        assert!(!style_at(&terminal, 5, 0).has(DisplayStyle::INVERSE));
+        assert!(!style_at(&terminal, 6, 0).has(DisplayStyle::HIDDEN));
+        assert_eq!(terminal.display.cells[0][7].style, None);
+    }
+
+    #[test]
+    fn sgr_underline_sub_parameters() {
+        let mut terminal = headless_terminal();
+        feed(
+            &mut terminal,
+            "\x1b[4ma\x1b[4:3mb\x1b[21mc\x1b[4:0md\x1b[4:5me",
+        );
+
+        assert!(style_at(&terminal, 0, 0).has(DisplayStyle::UNDERLINE));
+        let curly = style_at(&terminal, 1, 0);
+        assert!(curly.has(DisplayStyle::CURLY_UNDERLINE));
+        assert!(!curly.has(DisplayStyle::UNDERLINE));
+        assert!(style_at(&terminal, 2, 0).has(DisplayStyle::DOUBLE_UNDERLINE));
+        assert_eq!(terminal.display.cells[0][3].style, None);
+        assert!(style_at(&terminal, 4, 0).has(DisplayStyle::DASHED_UNDERLINE));
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[31;42ma\x1b[93;104mb\x1b[39;49mc");
+
+        let style = style_at(&terminal, 0, 0);
+        assert_eq!(style.foreground, TerminalColor::Named(NamedColor::Red));
+        assert_eq!(style.background, TerminalColor::Named(NamedColor::Green));
+        let style = style_at(&terminal, 1, 0);
+        assert_eq!(
+            style.foreground,
+            TerminalColor::Named(NamedColor::BrightYellow)
+        );
+        assert_eq!(
+            style.background,
+            TerminalColor::Named(NamedColor::BrightBlue)
+        );
+        assert_eq!(terminal.display.cells[0][2].style, None);
+    }
+
+    #[test]
+    fn sgr_extended_colors() {
+        let mut terminal = headless_terminal();
+        feed(
+            &mut terminal,
+            "\x1b[38;5;1ma\x1b[0;48;2;1;4;5mb\x1b[0;38:2::7:8:9mc\x1b[0;48:5:200;38:2:1:2:3md",
+        );
+
+        let style = style_at(&terminal, 0, 0);
+        assert_eq!(style.foreground, TerminalColor::Indexed(1));
+        assert!(!style.has(DisplayStyle::BLINK | DisplayStyle::BOLD));
+
+        let style = style_at(&terminal, 1, 0);
+        assert_eq!(style.background, TerminalColor::Rgb(1, 4, 5));
+        assert_eq!(style.style_metadata, 0);
+
+        assert_eq!(
+            style_at(&terminal, 2, 0).foreground,
+            TerminalColor::Rgb(7, 8, 9)
+        );
+
+        let style = style_at(&terminal, 3, 0);
+        assert_eq!(style.background, TerminalColor::Indexed(200));
+        assert_eq!(style.foreground, TerminalColor::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn sgr_invalid_extended_colors_are_ignored() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[38;5;300ma\x1b[48;2;1;2;999mb");
+
+        assert_eq!(terminal.display.cells[0][0].style, None);
+        assert_eq!(terminal.display.cells[0][1].style, None);
+    }
+
+    #[test]
+    fn screen_has_fixed_size() {
+        let terminal = headless_terminal();
+
+        assert_eq!(terminal.display.cells.len(), 24);
+        assert!(terminal.display.cells.iter().all(|row| row.len() == 80));
+    }
+
+    #[test]
+    fn absolute_cursor_positioning_is_one_based() {
+        let mut terminal = headless_terminal();
+
+        feed(&mut terminal, "\x1b[5;10H");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (9, 4));
+
+        feed(&mut terminal, "\x1b[3;7f");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (6, 2));
+
+        feed(&mut terminal, "\x1b[H");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+
+        feed(&mut terminal, "\x1b[12d");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 11));
+
+        feed(&mut terminal, "\x1b[20G");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (19, 11));
+
+        feed(&mut terminal, "\x1b[4`");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (3, 11));
+
+        feed(&mut terminal, "\x1b[0;0H");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+    }
+
+    #[test]
+    fn absolute_cursor_positioning_is_clamped() {
+        let mut terminal = headless_terminal();
+
+        feed(&mut terminal, "\x1b[100;200H");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (79, 23));
+
+        feed(&mut terminal, "\x1b[500d\x1b[500G");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (79, 23));
+    }
+
+    #[test]
+    fn relative_cursor_moves_do_not_underflow() {
+        let mut terminal = headless_terminal();
+
+        feed(&mut terminal, "\x1b[3;3H\x1b[10D\x1b[10A");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+
+        feed(&mut terminal, "\x1b[100C\x1b[100B");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (79, 23));
+
+        feed(&mut terminal, "\x1b[50F");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+
+        feed(&mut terminal, "\x1b[5C\x1b[2E");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 2));
+    }
+
+    #[test]
+    fn printing_at_a_position_overwrites_the_screen() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[2;3Habc\x1b[2;4HX");
+
+        assert_eq!(row_text(&terminal.display.cells[1]), "  aXc");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 1));
+    }
+
+    #[test]
+    fn printing_in_the_last_column_wraps_on_the_next_character() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;80Ha");
+
+        assert_eq!(terminal.display.cells[0][79].character, 'a');
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (79, 0));
+
+        feed(&mut terminal, "b");
+        assert_eq!(terminal.display.cells[1][0].character, 'b');
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (1, 1));
+    }
+
+    #[test]
+    fn scrolling_past_the_last_row_fills_the_scrollback() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "first\x1b[24;1Hlast\r\n");
+
+        assert_eq!(terminal.display.cells.len(), 24);
+        assert_eq!(terminal.scrollback.len(), 1);
+        assert_eq!(row_text(&terminal.scrollback[0]), "first");
+        assert_eq!(row_text(&terminal.display.cells[22]), "last");
+        assert_eq!(terminal.cursor_y, 23);
+        assert_eq!(terminal.display.top_displaying_row, 1);
+    }
+
+    #[test]
+    fn erase_keeps_the_screen_size() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "hello\x1b[2;1Hworld\x1b[1;3H\x1b[J");
+
+        assert_eq!(row_text(&terminal.display.cells[0]), "he");
+        assert_eq!(row_text(&terminal.display.cells[1]), "");
+
+        feed(&mut terminal, "\x1b[1;2H\x1b[1K");
+        assert_eq!(row_text(&terminal.display.cells[0]), "");
+        assert_eq!(terminal.display.cells.len(), 24);
+        assert!(terminal.display.cells.iter().all(|row| row.len() == 80));
+    }
+
+    #[test]
+    fn erase_uses_the_current_background() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;44mabc\x1b[1G\x1b[K");
+
+        let style = terminal.display.cells[0][0].style.unwrap();
+        assert_eq!(style.background, TerminalColor::Named(NamedColor::Blue));
+        assert!(!style.has(DisplayStyle::BOLD));
+    }
+
+    #[test]
+    fn alternate_screen_leaves_primary_screen_untouched() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "shell output\x1b[3;5H");
+
+        feed(&mut terminal, "\x1b[?1049h");
+        assert!(terminal.alternate_screen_active);
+        assert_eq!(row_text(&terminal.display.cells[0]), "");
+
+        feed(&mut terminal, "\x1b[1;1Hvim\x1b[10;10H");
+        assert_eq!(row_text(&terminal.display.cells[0]), "vim");
+
+        feed(&mut terminal, "\x1b[?1049l");
+        assert!(!terminal.alternate_screen_active);
+        assert_eq!(row_text(&terminal.display.cells[0]), "shell output");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 2));
+    }
+
+    #[test]
+    fn alternate_screen_does_not_scroll_into_scrollback() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?1049h\x1b[24;1Ha\r\n\r\nb");
+
+        assert!(terminal.scrollback.is_empty());
+        assert_eq!(row_text(&terminal.display.cells[21]), "a");
+
+        feed(&mut terminal, "\x1b[?1049l");
+        assert!(terminal.scrollback.is_empty());
+        assert!(terminal
+            .display
+            .cells
+            .iter()
+            .all(|row| row_text(row).is_empty()));
+    }
+
+    #[test]
+    fn alternate_screen_modes_47_and_1047() {
+        let mut terminal = headless_terminal();
+
+        // 47 keeps the contents of the alternate screen between switches
+        feed(&mut terminal, "\x1b[?47halt\x1b[?47l\x1b[?47h");
+        assert_eq!(row_text(&terminal.display.cells[0]), "alt");
+
+        // 1047 clears it when leaving
+        feed(&mut terminal, "\x1b[?47l\x1b[?1047h");
+        assert_eq!(row_text(&terminal.display.cells[0]), "alt");
+        feed(&mut terminal, "\x1b[?1047l\x1b[?1047h");
+        assert_eq!(row_text(&terminal.display.cells[0]), "");
+    }
+
+    fn fill_rows(terminal: &mut Terminal) {
+        for row in 0..24 {
+            feed(terminal, &format!("\x1b[{};1H{}", row + 1, row));
+        }
+    }
+
+    #[test]
+    fn scroll_region_only_scrolls_its_rows() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[5;10r");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+        assert_eq!(terminal.scroll_region, 4..10);
+
+        feed(&mut terminal, "\x1b[10;1H\x1bD");
+        assert_eq!(terminal.cursor_y, 9);
+        assert_eq!(row_text(&terminal.display.cells[3]), "3");
+        assert_eq!(row_text(&terminal.display.cells[4]), "5");
+        assert_eq!(row_text(&terminal.display.cells[8]), "9");
+        assert_eq!(row_text(&terminal.display.cells[9]), "");
+        assert_eq!(row_text(&terminal.display.cells[10]), "10");
+        assert!(terminal.scrollback.is_empty());
+    }
+
+    #[test]
+    fn reverse_index_scrolls_region_down() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[5;10r\x1b[5;1H\x1bM");
+        assert_eq!(terminal.cursor_y, 4);
+        assert_eq!(row_text(&terminal.display.cells[4]), "");
+        assert_eq!(row_text(&terminal.display.cells[5]), "4");
+        assert_eq!(row_text(&terminal.display.cells[9]), "8");
+        assert_eq!(row_text(&terminal.display.cells[10]), "10");
+
+        feed(&mut terminal, "\x1b[3;1H\x1bM");
+        assert_eq!(terminal.cursor_y, 1);
+        assert_eq!(row_text(&terminal.display.cells[2]), "2");
+    }
+
+    #[test]
+    fn scroll_up_and_down_sequences() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[2;4r\x1b[2S");
+        assert_eq!(row_text(&terminal.display.cells[0]), "0");
+        assert_eq!(row_text(&terminal.display.cells[1]), "3");
+        assert_eq!(row_text(&terminal.display.cells[2]), "");
+        assert_eq!(row_text(&terminal.display.cells[3]), "");
+        assert_eq!(row_text(&terminal.display.cells[4]), "4");
+
+        feed(&mut terminal, "\x1b[T");
+        assert_eq!(row_text(&terminal.display.cells[1]), "");
+        assert_eq!(row_text(&terminal.display.cells[2]), "3");
+        assert!(terminal.scrollback.is_empty());
+    }
+
+    #[test]
+    fn full_screen_region_scrolls_into_scrollback() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[r\x1b[2S");
+        assert_eq!(terminal.scroll_region, 0..24);
+        assert_eq!(terminal.scrollback.len(), 2);
+        assert_eq!(row_text(&terminal.scrollback[1]), "1");
+        assert_eq!(row_text(&terminal.display.cells[0]), "2");
+    }
+
+    #[test]
+    fn next_line_moves_to_the_start_of_the_next_line() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abc\x1bEd");
+
+        assert_eq!(row_text(&terminal.display.cells[1]), "d");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (1, 1));
+    }
+
+    #[test]
+    fn invalid_scroll_region_is_ignored() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[5;5H\x1b[10;5r");
+
+        assert_eq!(terminal.scroll_region, 0..24);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 4));
+    }
+
+    #[test]
+    fn cursor_up_and_down_stop_at_the_scroll_region() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[5;10r\x1b[7;1H\x1b[20A");
+        assert_eq!(terminal.cursor_y, 4);
+
+        feed(&mut terminal, "\x1b[20B");
+        assert_eq!(terminal.cursor_y, 9);
+
+        feed(&mut terminal, "\x1b[2;1H\x1b[20B");
+        assert_eq!(terminal.cursor_y, 9);
+    }
+
+    #[test]
+    fn insert_and_delete_characters() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abcdef\x1b[3G\x1b[2@");
+        assert_eq!(row_text(&terminal.display.cells[0]), "ab  cdef");
+        assert_eq!(terminal.cursor_x, 2);
+
+        feed(&mut terminal, "\x1b[3P");
+        assert_eq!(row_text(&terminal.display.cells[0]), "abdef");
+
+        feed(&mut terminal, "\x1b[P");
+        assert_eq!(row_text(&terminal.display.cells[0]), "abef");
+    }
+
+    #[test]
+    fn inserted_characters_fall_off_the_end_of_the_line() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;79Hxy\x1b[1;78H\x1b[@");
+
+        assert_eq!(terminal.display.cells[0].len(), 80);
+        assert_eq!(terminal.display.cells[0][78].character, ' ');
+        assert_eq!(terminal.display.cells[0][79].character, 'x');
+
+        feed(&mut terminal, "\x1b[100@");
+        assert!(row_text(&terminal.display.cells[0]).is_empty());
+        assert_eq!(terminal.display.cells[0].len(), 80);
+    }
+
+    #[test]
+    fn deleted_characters_are_filled_with_the_current_background() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abc\x1b[1G\x1b[41m\x1b[P");
+
+        assert_eq!(row_text(&terminal.display.cells[0]), "bc");
+        let style = terminal.display.cells[0][79].style.unwrap();
+        assert_eq!(style.background, TerminalColor::Named(NamedColor::Red));
+        assert_eq!(terminal.display.cells[0][1].style, None);
+    }
+
+    #[test]
+    fn insert_and_delete_lines_within_the_scroll_region() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[5;10r\x1b[6;4H\x1b[2L");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 5));
+        assert_eq!(row_text(&terminal.display.cells[4]), "4");
+        assert_eq!(row_text(&terminal.display.cells[5]), "");
+        assert_eq!(row_text(&terminal.display.cells[6]), "");
+        assert_eq!(row_text(&terminal.display.cells[7]), "5");
+        assert_eq!(row_text(&terminal.display.cells[9]), "7");
+        assert_eq!(row_text(&terminal.display.cells[10]), "10");
+
+        feed(&mut terminal, "\x1b[3M");
+        assert_eq!(row_text(&terminal.display.cells[5]), "6");
+        assert_eq!(row_text(&terminal.display.cells[6]), "7");
+        assert_eq!(row_text(&terminal.display.cells[7]), "");
+        assert_eq!(row_text(&terminal.display.cells[9]), "");
+        assert_eq!(row_text(&terminal.display.cells[10]), "10");
+    }
+
+    #[test]
+    fn insert_lines_outside_the_scroll_region_is_ignored() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+
+        feed(&mut terminal, "\x1b[5;10r\x1b[2;1H\x1b[L\x1b[M");
+        assert_eq!(row_text(&terminal.display.cells[1]), "1");
+        assert_eq!(row_text(&terminal.display.cells[2]), "2");
+    }
+
+    #[test]
+    fn erase_characters_does_not_shift() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abcdef\x1b[2G\x1b[3X");
+        assert_eq!(row_text(&terminal.display.cells[0]), "a   ef");
+        assert_eq!(terminal.cursor_x, 1);
+
+        feed(&mut terminal, "\x1b[1;78H\x1b[10X");
+        assert_eq!(terminal.display.cells[0].len(), 80);
+    }
+
+    #[test]
+    fn repeat_last_character() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[31m-\x1b[4b");
+
+        assert_eq!(row_text(&terminal.display.cells[0]), "-----");
+        assert_eq!(
+            style_at(&terminal, 4, 0).foreground,
+            TerminalColor::Named(NamedColor::Red)
+        );
+    }
+
+    #[test]
+    fn insert_mode_shifts_printed_characters() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "world\x1b[1G\x1b[4hhello \x1b[4l!");
+
+        assert_eq!(row_text(&terminal.display.cells[0]), "hello !orld");
+    }
+
+    #[test]
+    fn resize_changes_the_screen_size() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "hello\x1b[5;70H");
+        terminal.resize(30, 100);
+
+        assert_eq!((terminal.rows, terminal.columns), (30, 100));
+        assert_eq!(terminal.display.cells.len(), 30);
+        assert!(terminal.display.cells.iter().all(|row| row.len() == 100));
+        assert_eq!(row_text(&terminal.display.cells[0]), "hello");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (69, 4));
+        assert_eq!(terminal.scroll_region, 0..30);
+
+        terminal.resize(10, 40);
+        assert!(terminal.display.cells.iter().all(|row| row.len() == 40));
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (39, 4));
+        assert!(terminal.scrollback.is_empty());
+    }
+
+    #[test]
+    fn shrinking_moves_lines_above_the_cursor_into_the_scrollback() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+        feed(&mut terminal, "\x1b[20;1H");
+
+        terminal.resize(10, 80);
+        assert_eq!(terminal.scrollback.len(), 10);
+        assert_eq!(row_text(&terminal.scrollback[0]), "0");
+        assert_eq!(row_text(&terminal.display.cells[0]), "10");
+        assert_eq!(terminal.cursor_y, 9);
+        assert_eq!(terminal.display.top_displaying_row, 10);
+
+        // growing brings them back
+        terminal.resize(24, 80);
+        assert!(terminal.scrollback.is_empty());
+        assert_eq!(row_text(&terminal.display.cells[0]), "0");
+        assert_eq!(terminal.cursor_y, 19);
+    }
+
+    #[test]
+    fn resize_keeps_the_primary_screen_behind_the_alternate_one() {
+        let mut terminal = headless_terminal();
+        fill_rows(&mut terminal);
+        feed(&mut terminal, "\x1b[24;1H\x1b[?1049h");
+
+        terminal.resize(12, 80);
+        assert_eq!(terminal.display.cells.len(), 12);
+        assert_eq!(terminal.scrollback.len(), 12);
+
+        feed(&mut terminal, "\x1b[?1049l");
+        assert_eq!(row_text(&terminal.display.cells[11]), "23");
+        assert_eq!(terminal.cursor_y, 11);
+    }
+
+    #[test]
+    fn window_size_matches_the_cells() {
+        let terminal = headless_terminal();
+        let size = window_size(terminal.rows, terminal.columns, &terminal.display);
+
+        assert_eq!((size.ws_row, size.ws_col), (24, 80));
+        assert_eq!((size.ws_xpixel, size.ws_ypixel), (640, 384));
+    }
+
+    #[test]
+    fn set_window_size_reaches_the_slave() {
+        let pty = openpty(None, None).unwrap();
+        let size = Winsize {
+            ws_row: 12,
+            ws_col: 34,
+            ws_xpixel: 56,
+            ws_ypixel: 78,
+        };
+        set_window_size(pty.master.as_raw_fd(), &size).unwrap();
+
+        let mut slave_size = Winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let res = unsafe {
+            ioctl(
+                pty.slave.as_raw_fd(),
+                nix::libc::TIOCGWINSZ,
+                &mut slave_size as *mut Winsize,
+            )
+        };
+        assert_eq!(res, 0);
+        assert_eq!(
+            (slave_size.ws_row, slave_size.ws_col, slave_size.ws_xpixel),
+            (12, 34, 56)
+        );
+    }
+
+    #[test]
+    fn wrapped_lines_are_marked() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, &"x".repeat(100));
+        feed(&mut terminal, "\r\n");
+
+        assert!(terminal.display.cells[0].wrapped);
+        assert!(!terminal.display.cells[1].wrapped);
+
+        feed(&mut terminal, "\x1b[1;1H\x1b[K");
+        assert!(!terminal.display.cells[0].wrapped);
+    }
+
+    #[test]
+    fn narrowing_rewraps_lines() {
+        let mut terminal = headless_terminal();
+        let text = "0123456789".repeat(10);
+        feed(&mut terminal, &text);
+
+        terminal.resize(24, 50);
+        assert_eq!(row_text(&terminal.display.cells[0]), text[..50]);
+        assert_eq!(row_text(&terminal.display.cells[1]), text[50..]);
+        assert!(terminal.display.cells[0].wrapped);
+        assert!(terminal.display.cells[1].wrapped);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 2));
+
+        feed(&mut terminal, "!");
+        assert_eq!(row_text(&terminal.display.cells[2]), "!");
+    }
+
+    #[test]
+    fn widening_joins_wrapped_lines() {
+        let mut terminal = headless_terminal();
+        let text = "0123456789".repeat(10);
+        feed(&mut terminal, &text);
+        feed(&mut terminal, "\r\nnext");
+
+        terminal.resize(24, 120);
+        assert_eq!(row_text(&terminal.display.cells[0]), text);
+        assert!(!terminal.display.cells[0].wrapped);
+        assert_eq!(row_text(&terminal.display.cells[1]), "next");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 1));
+    }
+
+    #[test]
+    fn reflow_keeps_the_cursor_on_the_same_character() {
+        let mut terminal = headless_terminal();
+        let text = "abcdefghijklmnopqrstuvwxyz".repeat(4);
+        feed(&mut terminal, &text);
+        feed(&mut terminal, "\x1b[2;5H");
+        assert_eq!(terminal.display.cells[1][4].character, 'g');
+
+        terminal.resize(24, 30);
+        let cell = terminal.display.cells[terminal.cursor_y][terminal.cursor_x];
+        assert_eq!(cell.character, 'g');
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (24, 2));
+    }
+
+    #[test]
+    fn reflow_includes_the_scrollback() {
+        let mut terminal = headless_terminal();
+        let text = "0123456789".repeat(9);
+        for _ in 0..30 {
+            feed(&mut terminal, &text);
+            feed(&mut terminal, "\r\n");
+        }
+        assert_eq!(terminal.scrollback.len(), 37);
+
+        terminal.resize(24, 100);
+        assert_eq!(terminal.scrollback.len(), 7);
+        assert!(terminal
+            .scrollback
+            .iter()
+            .all(|row| row_text(row) == text && row.len() == 100));
+        assert_eq!(terminal.cursor_y, 23);
+        assert_eq!(terminal.display.top_displaying_row, 7);
+    }
+
+    // prints numbered lines at the bottom of the screen, scrolling once after each of them
+    fn scroll_numbered_lines(terminal: &mut Terminal, lines: std::ops::Range<usize>) {
+        for line in lines {
+            feed(terminal, &format!("\x1b[24;1H{}\x1bD", line));
+        }
+    }
+
+    #[test]
+    fn scrollback_evicts_past_its_limit() {
+        let mut terminal = headless_terminal();
+        terminal.set_scrollback_limit(5);
+        scroll_numbered_lines(&mut terminal, 0..40);
+
+        assert_eq!(terminal.scrollback.len(), 5);
+        assert_eq!(terminal.display.top_displaying_row, 5);
+        let texts: Vec<String> = terminal
+            .scrollback
+            .iter()
+            .map(|row| row_text(row))
+            .collect();
+        assert_eq!(texts, ["12", "13", "14", "15", "16"]);
+        assert_eq!(row_text(&terminal.display.cells[0]), "17");
+    }
+
+    #[test]
+    fn eviction_keeps_a_scrolled_back_view_in_place() {
+        let mut terminal = headless_terminal();
+        terminal.set_scrollback_limit(5);
+        scroll_numbered_lines(&mut terminal, 0..29);
+
+        // scrolled back onto the row with "3"
+        terminal.display.top_displaying_row = 2;
+        scroll_numbered_lines(&mut terminal, 29..30);
+        assert_eq!(terminal.display.top_displaying_row, 1);
+        let display = terminal.rendered_display();
+        let top = display.displayed_rows().next().unwrap();
+        assert_eq!(row_text(top), "3");
+
+        terminal.set_scrollback_limit(4);
+        assert_eq!(terminal.display.top_displaying_row, 0);
+        let display = terminal.rendered_display();
+        let top = display.displayed_rows().next().unwrap();
+        assert_eq!(row_text(top), "3");
+    }
+
+    #[test]
+    fn c0_controls() {
+        // (description, input, expected first row, expected cursor)
+        let cases: &[(&str, &str, &str, (usize, usize))] = &[
+            ("carriage return overwrites", "a\rb", "b", (1, 0)),
+            ("line feed keeps the column", "ab\ncd", "ab", (4, 1)),
+            ("vertical tab is a line feed", "ab\x0bc", "ab", (3, 1)),
+            ("form feed is a line feed", "ab\x0cc", "ab", (3, 1)),
+            ("crlf starts a new line", "ab\r\ncd", "ab", (2, 1)),
+            ("backspace moves left", "abc\x08\x08X", "aXc", (2, 0)),
+            (
+                "backspace stops at the first column",
+                "\x08\x08a",
+                "a",
+                (1, 0),
+            ),
+            ("tab moves to the next stop", "a\tb", "a       b", (9, 0)),
+            (
+                "tab from a stop",
+                "\x1b[1;9H\tb",
+                "                b",
+                (17, 0),
+            ),
+            (
+                "tab stops at the last column",
+                "\x1b[1;78H\t\tb",
+                &format!("{}b", " ".repeat(79)),
+                (79, 0),
+            ),
+            ("bell does not move the cursor", "a\x07b", "ab", (2, 0)),
+            ("shift out uses g1", "\x1b)0a\x0eq\x0fq", "a─q", (3, 0)),
+            ("g0 line drawing", "\x1b(0lqk\x1b(Bq", "┌─┐q", (4, 0)),
+            (
+                "newline mode returns the cursor",
+                "\x1b[20hab\ncd",
+                "ab",
+                (2, 1),
+            ),
+        ];
+
+        for (description, input, row, cursor) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, input);
+
+            assert_eq!(
+                row_text(&terminal.display.cells[0]),
+                *row,
+                "{}",
+                description
+            );
+            assert_eq!(
+                (terminal.cursor_x, terminal.cursor_y),
+                *cursor,
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn carriage_return_clears_a_pending_wrap() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, &"x".repeat(80));
+        assert!(terminal.wrap_pending);
+
+        feed(&mut terminal, "\ry");
+        assert_eq!(terminal.display.cells[0][0].character, 'y');
+        assert_eq!(terminal.cursor_y, 0);
+    }
+
+    #[test]
+    fn bell_raises_an_event() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x07\x07");
+
+        assert_eq!(
+            terminal.take_events(),
+            [TerminalEvent::Bell, TerminalEvent::Bell]
+        );
+        assert!(terminal.take_events().is_empty());
+    }
+
+    #[test]
+    fn tab_stops() {
+        // (description, input, expected cursor column)
+        let cases: &[(&str, &str, usize)] = &[
+            ("default stops", "\t\t", 16),
+            ("set a stop", "\x1b[1;4H\x1bH\x1b[1;1H\t", 3),
+            (
+                "clear the stop at the cursor",
+                "\x1b[1;9H\x1b[g\x1b[1;1H\t",
+                16,
+            ),
+            ("clear every stop", "\x1b[3g\t", 79),
+            ("forward tabulation", "\x1b[3I", 24),
+            ("forward tabulation defaults to one", "\x1b[I", 8),
+            ("backward tabulation", "\x1b[1;20H\x1b[2Z", 8),
+            (
+                "backward tabulation stops at the first column",
+                "\x1b[1;20H\x1b[5Z",
+                0,
+            ),
+        ];
+
+        for (description, input, column) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, input);
+
+            assert_eq!(terminal.cursor_x, *column, "{}", description);
+        }
+    }
+
+    #[test]
+    fn tab_stops_follow_the_column_count() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[1;4H\x1bH");
+
+        terminal.resize(24, 100);
+        assert_eq!(terminal.tab_stops.len(), 100);
+        assert!(terminal.tab_stops[3]);
+        assert!(terminal.tab_stops[88]);
+        assert!(!terminal.tab_stops[89]);
+
+        terminal.resize(24, 10);
+        assert_eq!(terminal.tab_stops.len(), 10);
+        feed(&mut terminal, "\x1b[1;9H\t");
+        assert_eq!(terminal.cursor_x, 9);
+    }
+
+    #[test]
+    fn copying_gives_back_tabs() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "a\tb\t\tc\r\n1234567890\tx");
+
+        let row = &terminal.display.cells[0];
+        assert_eq!(row_text(row), "a       b               c");
+        assert!(row[1].tab && row[9].tab && row[16].tab);
+        assert_eq!(terminal.copy_row_text(row, 0..80), "a\tb\t\tc");
+        assert_eq!(terminal.copy_row_text(row, 1..9), "\tb");
+
+        // the tab went over cells that were already written, so it is not kept
+        feed(&mut terminal, "\r\nabcdefghij\r\x1b[2C\t");
+        let row = &terminal.display.cells[2];
+        assert_eq!(terminal.copy_row_text(row, 0..80), "abcdefghij");
+
+        let row = &terminal.display.cells[1];
+        assert_eq!(terminal.copy_row_text(row, 0..80), "1234567890\tx");
+    }
+
+    #[test]
+    fn save_and_restore_the_cursor() {
+        // (description, input)
+        let cases: &[(&str, &str)] = &[("decsc and decrc", "\x1b7"), ("csi s and u", "\x1b[s")];
+
+        for (description, save) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, "\x1b[3;5H\x1b[1;31m\x1b(0\x1b[?7l");
+            feed(&mut terminal, save);
+            feed(&mut terminal, "\x1b[10;20H\x1b[0m\x1b(B\x1b[?7h");
+
+            let restore = if *save == "\x1b7" { "\x1b8" } else { "\x1b[u" };
+            feed(&mut terminal, restore);
+            assert_eq!(
+                (terminal.cursor_x, terminal.cursor_y),
+                (4, 2),
+                "{}",
+                description
+            );
+            feed(&mut terminal, "q");
+            let style = style_at(&terminal, 4, 2);
+            assert!(style.has(DisplayStyle::BOLD), "{}", description);
+            assert_eq!(
+                terminal.display.cells[2][4].character, '─',
+                "{}",
+                description
+            );
+            assert!(!terminal.modes.has(Modes::AUTOWRAP), "{}", description);
+        }
+    }
+
+    #[test]
+    fn restoring_keeps_a_pending_wrap() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, &"x".repeat(80));
+        feed(&mut terminal, "\x1b7\x1b[5;5H\x1b8y");
+
+        assert_eq!(terminal.display.cells[1][0].character, 'y');
+        assert!(terminal.display.cells[0].wrapped);
+    }
+
+    #[test]
+    fn restoring_without_a_save_resets_the_cursor() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[5;5H\x1b[1m\x1b(0\x1b8");
+
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+        assert!(terminal.current_style.is_none());
+        assert_eq!(terminal.charsets, [Charset::Ascii; 2]);
+    }
+
+    #[test]
+    fn each_screen_has_its_own_saved_cursor() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[2;2H\x1b7");
+        feed(&mut terminal, "\x1b[?47h\x1b[8;8H\x1b7\x1b[1;1H\x1b8");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (7, 7));
+
+        feed(&mut terminal, "\x1b[?47l\x1b8");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (1, 1));
+    }
+
+    #[test]
+    fn origin_mode_is_saved_with_the_cursor() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[5;10r\x1b[?6h");
+        assert_eq!(terminal.cursor_y, 4);
+        feed(&mut terminal, "\x1b[2;1H");
+        assert_eq!(terminal.cursor_y, 5);
+
+        feed(&mut terminal, "\x1b7\x1b[?6l\x1b[2;1H");
+        assert_eq!(terminal.cursor_y, 1);
+        feed(&mut terminal, "\x1b8\x1b[20;1H");
+        assert!(terminal.modes.has(Modes::ORIGIN));
+        assert_eq!(terminal.cursor_y, 9);
+    }
+
+    #[test]
+    fn replies_to_queries() {
+        // (description, input, expected reply)
+        let version = VERSION.split('.').fold(0, |version, part| {
+            version * 100 + part.parse::<u32>().unwrap()
+        });
+        let cases: &[(&str, &str, String)] = &[
+            ("status report", "\x1b[5n", "\x1b[0n".to_string()),
+            (
+                "cursor position",
+                "\x1b[3;7H\x1b[6n",
+                "\x1b[3;7R".to_string(),
+            ),
+            (
+                "dec cursor position",
+                "\x1b[3;7H\x1b[?6n",
+                "\x1b[?3;7R".to_string(),
+            ),
+            (
+                "cursor position in origin mode",
+                "\x1b[5;10r\x1b[?6h\x1b[2;3H\x1b[6n",
+                "\x1b[2;3R".to_string(),
+            ),
+            ("primary attributes", "\x1b[c", "\x1b[?62;22c".to_string()),
+            (
+                "primary attributes with 0",
+                "\x1b[0c",
+                "\x1b[?62;22c".to_string(),
+            ),
+            (
+                "secondary attributes",
+                "\x1b[>c",
+                format!("\x1b[>1;{};0c", version),
+            ),
+            (
+                "version",
+                "\x1b[>q",
+                format!("\x1bP>|vigil({})\x1b\\", VERSION),
+            ),
+            ("set dec mode", "\x1b[?7$p", "\x1b[?7;1$y".to_string()),
+            ("reset dec mode", "\x1b[?6$p", "\x1b[?6;2$y".to_string()),
+            (
+                "unknown dec mode",
+                "\x1b[?9999$p",
+                "\x1b[?9999;0$y".to_string(),
+            ),
+            ("ansi mode", "\x1b[4h\x1b[4$p", "\x1b[4;1$y".to_string()),
+            ("unknown ansi mode", "\x1b[7$p", "\x1b[7;0$y".to_string()),
+            (
+                "no reply to unknown reports",
+                "\x1b[99n\x1b[1c",
+                String::new(),
+            ),
+        ];
+
+        for (description, input, reply) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, input);
+
+            assert_eq!(
+                String::from_utf8(terminal.take_responses()).unwrap(),
+                *reply,
+                "{}",
+                description
+            );
+            assert!(terminal.take_responses().is_empty(), "{}", description);
+        }
+    }
+
+    #[test]
+    fn modes() {
+        // (description, input, mode, expected state)
+        let cases: &[(&str, &str, usize, bool)] = &[
+            ("cursor keys", "\x1b[?1h", Modes::CURSOR_KEYS, true),
+            ("reverse video", "\x1b[?5h", Modes::REVERSE_VIDEO, true),
+            ("origin", "\x1b[?6h", Modes::ORIGIN, true),
+            ("autowrap is on by default", "", Modes::AUTOWRAP, true),
+            ("autowrap off", "\x1b[?7l", Modes::AUTOWRAP, false),
+            (
+                "cursor is visible by default",
+                "",
+                Modes::CURSOR_VISIBLE,
+                true,
+            ),
+            ("hide the cursor", "\x1b[?25l", Modes::CURSOR_VISIBLE, false),
+            ("insert", "\x1b[4h", Modes::INSERT, true),
+            ("newline", "\x1b[20h", Modes::NEWLINE, true),
+            (
+                "every parameter is a mode",
+                "\x1b[?1;25;5l\x1b[?25;1h",
+                Modes::CURSOR_KEYS,
+                true,
+            ),
+            ("private 4 is not insert", "\x1b[?4h", Modes::INSERT, false),
+            (
+                "ansi 1 is not cursor keys",
+                "\x1b[1h",
+                Modes::CURSOR_KEYS,
+                false,
+            ),
+            ("ansi 7 is not autowrap", "\x1b[7l", Modes::AUTOWRAP, true),
+            (
+                "other intermediates are ignored",
+                "\x1b[>1h",
+                Modes::CURSOR_KEYS,
+                false,
+            ),
+        ];
+
+        for (description, input, mode, state) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, input);
+
+            assert_eq!(terminal.modes.has(*mode), *state, "{}", description);
+        }
+    }
+
+    #[test]
+    fn modes_are_reported() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?1;5;6h\x1b[?7;25l");
+        feed(
+            &mut terminal,
+            "\x1b[?1$p\x1b[?5$p\x1b[?6$p\x1b[?7$p\x1b[?25$p",
+        );
+
+        assert_eq!(
+            String::from_utf8(terminal.take_responses()).unwrap(),
+            "\x1b[?1;1$y\x1b[?5;1$y\x1b[?6;1$y\x1b[?7;2$y\x1b[?25;2$y"
+        );
+    }
+
+    #[test]
+    fn reverse_video_swaps_the_default_colors() {
+        let mut terminal = headless_terminal();
+        let palette = &terminal.display.palette;
+        let (foreground, background) = (palette.foreground, palette.background);
+
+        feed(&mut terminal, "\x1b[?5h");
+        let palette = &terminal.display.palette;
+        assert_eq!(palette.default_foreground(), background);
+        assert_eq!(palette.default_background(), foreground);
+
+        feed(&mut terminal, "\x1b[?5l");
+        assert_eq!(terminal.display.palette.default_foreground(), foreground);
+    }
+
+    fn mouse(action: MouseAction, column: usize, row: usize) -> MouseInput {
+        MouseInput {
+            action,
+            column,
+            row,
+            shift: false,
+            alt: false,
+            control: false,
+        }
+    }
+
+    #[test]
+    fn mouse_reports() {
+        use crate::app::display::{MouseAction::*, MouseButton::*};
+
+        // (description, modes, input, expected report)
+        let cases: &[(&str, &str, MouseInput, &[u8])] = &[
+            ("untracked", "", mouse(Press(Left), 0, 0), b""),
+            (
+                "press",
+                "\x1b[?1000h",
+                mouse(Press(Left), 2, 3),
+                b"\x1b[M #$",
+            ),
+            (
+                "release",
+                "\x1b[?1000h",
+                mouse(Release(Right), 0, 0),
+                b"\x1b[M#!!",
+            ),
+            ("wheel", "\x1b[?1000h", mouse(WheelUp, 0, 0), b"\x1b[M`!!"),
+            (
+                "normal ignores drags",
+                "\x1b[?1000h",
+                mouse(Move(Some(Left)), 0, 0),
+                b"",
+            ),
+            (
+                "drag",
+                "\x1b[?1002h",
+                mouse(Move(Some(Middle)), 0, 0),
+                b"\x1b[MA!!",
+            ),
+            (
+                "button event ignores motion",
+                "\x1b[?1002h",
+                mouse(Move(None), 0, 0),
+                b"",
+            ),
+            (
+                "motion",
+                "\x1b[?1003h",
+                mouse(Move(None), 0, 0),
+                b"\x1b[MC!!",
+            ),
+            (
+                "new tracking replaces",
+                "\x1b[?1003h\x1b[?1000h",
+                mouse(Move(None), 0, 0),
+                b"",
+            ),
+            (
+                "reset",
+                "\x1b[?1000h\x1b[?1000l",
+                mouse(Press(Left), 0, 0),
+                b"",
+            ),
+            ("too far", "\x1b[?1000h", mouse(Press(Left), 300, 0), b""),
+            (
+                "sgr press",
+                "\x1b[?1000;1006h",
+                mouse(Press(Right), 299, 9),
+                b"\x1b[<2;300;10M",
+            ),
+            (
+                "sgr release",
+                "\x1b[?1000;1006h",
+                mouse(Release(Right), 0, 0),
+                b"\x1b[<2;1;1m",
+            ),
+            (
+                "sgr wheel",
+                "\x1b[?1000;1006h",
+                mouse(WheelDown, 4, 4),
+                b"\x1b[<65;5;5M",
+            ),
+            (
+                "sgr drag",
+                "\x1b[?1002;1006h",
+                mouse(Move(Some(Left)), 0, 0),
+                b"\x1b[<32;1;1M",
+            ),
+            (
+                "urxvt",
+                "\x1b[?1000;1015h",
+                mouse(Press(Left), 299, 0),
+                b"\x1b[32;300;1M",
+            ),
+            (
+                "urxvt release",
+                "\x1b[?1000;1015h",
+                mouse(Release(Left), 0, 0),
+                b"\x1b[35;1;1M",
+            ),
+            (
+                "sgr over urxvt",
+                "\x1b[?1000;1015;1006h",
+                mouse(Press(Left), 0, 0),
+                b"\x1b[<0;1;1M",
+            ),
+        ];
+
+        for (description, modes, input, report) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, modes);
+            terminal.report_mouse(*input);
+
+            assert_eq!(terminal.take_responses(), *report, "{}", description);
+        }
+    }
+
+    #[test]
+    fn mouse_modifiers() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?1000;1006h");
+        terminal.report_mouse(MouseInput {
+            shift: true,
+            alt: true,
+            control: true,
+            ..mouse(MouseAction::Press(MouseButton::Left), 0, 0)
+        });
+
+        assert_eq!(terminal.take_responses(), b"\x1b[<28;1;1M");
+    }
+
+    #[test]
+    fn tracking_is_published_to_the_display() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?1006h");
+        assert!(!terminal.display.mouse_tracking);
+
+        feed(&mut terminal, "\x1b[?1002h");
+        assert!(terminal.display.mouse_tracking);
+        feed(&mut terminal, "\x1b[?1002l");
+        assert!(!terminal.display.mouse_tracking);
+    }
+
+    #[test]
+    fn paste() {
+        // (description, modes, text, expected bytes)
+        let cases: &[(&str, &str, &str, &str)] = &[
+            ("plain", "", "echo hi", "echo hi"),
+            ("new lines become returns", "", "a\nb\r\nc", "a\rb\rc"),
+            ("bracketed", "\x1b[?2004h", "a\nb", "\x1b[200~a\nb\x1b[201~"),
+            (
+                "end markers are removed",
+                "\x1b[?2004h",
+                "a\x1b[201~rm -rf ~\n",
+                "\x1b[200~arm -rf ~\n\x1b[201~",
+            ),
+            (
+                "nested end markers are removed",
+                "\x1b[?2004h",
+                "\x1b[20\x1b[201~1~x",
+                "\x1b[200~x\x1b[201~",
+            ),
+            (
+                "bracketed paste off",
+                "\x1b[?2004h\x1b[?2004l",
+                "a\x1b[201~",
+                "a\x1b[201~",
+            ),
+        ];
+
+        for (description, modes, text, bytes) in cases {
+            let mut terminal = headless_terminal();
+            feed(&mut terminal, modes);
+
+            assert_eq!(
+                String::from_utf8(terminal.encode_paste(text)).unwrap(),
+                *bytes,
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn keys_follow_the_modes() {
+        let mut terminal = headless_terminal();
+        let up = KeyInput {
+            key: TerminalKey::Up,
+            base_key: None,
+            text: None,
+            numpad: false,
+            released: false,
+            shift: false,
+            alt: false,
+            control: false,
+        };
+        let keypad_one = KeyInput {
+            key: TerminalKey::Character("1".to_string()),
+            text: Some("1".to_string()),
+            numpad: true,
+            ..up.clone()
+        };
+
+        assert_eq!(terminal.encode_key(&up), b"\x1b[A");
+        feed(&mut terminal, "\x1b[?1h\x1b=");
+        assert_eq!(terminal.encode_key(&up), b"\x1bOA");
+        assert_eq!(terminal.encode_key(&keypad_one), b"\x1bOq");
+
+        feed(&mut terminal, "\x1b[?1l\x1b>");
+        assert_eq!(terminal.encode_key(&up), b"\x1b[A");
+        assert_eq!(terminal.encode_key(&keypad_one), b"1");
+    }
+
+    #[test]
+    fn keyboard_flag_stack() {
+        let mut terminal = headless_terminal();
+        let query = |terminal: &mut Terminal| {
+            feed(terminal, "\x1b[?u");
+            String::from_utf8(terminal.take_responses()).unwrap()
+        };
+
+        assert_eq!(query(&mut terminal), "\x1b[?0u");
+        feed(&mut terminal, "\x1b[>1u\x1b[>31u");
+        assert_eq!(query(&mut terminal), "\x1b[?31u");
+        feed(&mut terminal, "\x1b[<u");
+        assert_eq!(query(&mut terminal), "\x1b[?1u");
+
+        // set, add and remove flags
+        feed(&mut terminal, "\x1b[=8u");
+        assert_eq!(terminal.current_keyboard_flags(), 8);
+        feed(&mut terminal, "\x1b[=3;2u");
+        assert_eq!(terminal.current_keyboard_flags(), 11);
+        feed(&mut terminal, "\x1b[=2;3u");
+        assert_eq!(terminal.current_keyboard_flags(), 9);
+
+        // popping more than there is empties the stack
+        feed(&mut terminal, "\x1b[<5u");
+        assert_eq!(terminal.current_keyboard_flags(), 0);
+        assert!(terminal.keyboard_flags[0].is_empty());
+    }
+
+    #[test]
+    fn keyboard_flags_are_per_screen() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[>1u\x1b[?1049h");
+        assert_eq!(terminal.current_keyboard_flags(), 0);
+
+        feed(&mut terminal, "\x1b[>8u\x1b[?1049l");
+        assert_eq!(terminal.current_keyboard_flags(), 1);
+    }
+
+    #[test]
+    fn keyboard_flag_stack_is_bounded() {
+        let mut terminal = headless_terminal();
+        for flags in 0..20 {
+            feed(&mut terminal, &format!("\x1b[>{}u", flags % 32));
+        }
+
+        assert_eq!(terminal.keyboard_flags[0].len(), KEYBOARD_STACK_SIZE);
+        assert_eq!(terminal.keyboard_flags[0][0], 4);
+        assert_eq!(terminal.current_keyboard_flags(), 19);
+    }
+
+    #[test]
+    fn keys_use_the_keyboard_flags() {
+        let mut terminal = headless_terminal();
+        let escape = KeyInput {
+            key: TerminalKey::Escape,
+            base_key: None,
+            text: None,
+            numpad: false,
+            released: false,
+            shift: false,
+            alt: false,
+            control: false,
+        };
+
+        assert_eq!(terminal.encode_key(&escape), b"\x1b");
+        feed(&mut terminal, "\x1b[>1u");
+        assert_eq!(terminal.encode_key(&escape), b"\x1b[27u");
+    }
+
+    #[test]
+    fn pending_input_is_written_partially() {
+        let (sender, mut reciever) = UnixStream::pair().unwrap();
+        sender.set_nonblocking(true).unwrap();
+
+        // more than the socket buffer takes at once
+        let mut pending: Vec<u8> = (0..4 * 1024 * 1024).map(|byte| byte as u8).collect();
+        let total = pending.len();
+        write_pending(sender.as_raw_fd(), &mut pending).unwrap();
+        assert!(!pending.is_empty());
+        assert!(pending.len() < total);
+
+        // what was written comes first and in order
+        let mut received = vec![0; 16];
+        reciever.read_exact(&mut received).unwrap();
+        assert_eq!(received, (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn pending_input_is_sent_whole() {
+        let (sender, mut reciever) = UnixStream::pair().unwrap();
+        sender.set_nonblocking(true).unwrap();
+
+        let mut pending = "héllo wörld ✓".as_bytes().to_vec();
+        write_pending(sender.as_raw_fd(), &mut pending).unwrap();
+        assert!(pending.is_empty());
+
+        let mut received = vec![0; "héllo wörld ✓".len()];
+        reciever.read_exact(&mut received).unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), "héllo wörld ✓");
+    }
+
+    #[test]
+    fn focus_is_reported_in_focus_event_mode() {
+        let mut terminal = headless_terminal();
+        terminal.set_focus(false);
+        assert!(!terminal.display.focused);
+        assert!(terminal.take_responses().is_empty());
+
+        feed(&mut terminal, "\x1b[?1004h");
+        terminal.set_focus(true);
+        terminal.set_focus(true);
+        terminal.set_focus(false);
+        assert_eq!(terminal.take_responses(), b"\x1b[I\x1b[O");
+
+        feed(&mut terminal, "\x1b[?1004l");
+        terminal.set_focus(true);
+        assert!(terminal.take_responses().is_empty());
+        assert!(terminal.display.focused);
+    }
+
+    #[test]
+    fn cursor_is_drawn_where_it_is_visible() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[5;10H");
+        assert_eq!(terminal.visible_cursor(), Some((9, 4)));
+
+        feed(&mut terminal, "\x1b[?25l");
+        assert_eq!(terminal.visible_cursor(), None);
+        feed(&mut terminal, "\x1b[?25h");
+
+        assert_eq!(terminal.rendered_display().cursor_cell(), Some((9, 4)));
+
+        // scrolled back, the cursor moves down with the screen until it leaves the widget
+        for line in 0..30 {
+            feed(&mut terminal, &format!("{}\r\n", line));
+        }
+        feed(&mut terminal, "\x1b[5;10H");
+        terminal.display.top_displaying_row -= 1;
+        assert_eq!(terminal.rendered_display().cursor_cell(), Some((9, 5)));
+
+        feed(&mut terminal, "\x1b[24;10H");
+        assert_eq!(terminal.rendered_display().cursor_cell(), None);
+    }
+
+    #[test]
+    fn synchronized_output_freezes_the_display() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "before\x1b[?2026h\r\x1b[2Kafter");
+
+        let frozen = terminal.rendered_display();
+        assert!(row_text(&frozen.cells[0]).starts_with("before"));
+        assert_eq!(frozen.cursor, Some((6, 0)));
+        assert!(row_text(&terminal.display.cells[0]).starts_with("after"));
+
+        feed(&mut terminal, "\x1b[?2026$p");
+        assert_eq!(terminal.take_responses(), b"\x1b[?2026;1$y");
+
+        feed(&mut terminal, "\x1b[?2026l");
+        let shown = terminal.rendered_display();
+        assert!(row_text(&shown.cells[0]).starts_with("after"));
+        assert_eq!(shown.cursor, Some((5, 0)));
+    }
+
+    #[test]
+    fn synchronized_output_times_out() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?2026h");
+        let (_, started) = terminal.synchronized_frame.as_ref().unwrap();
+        let started = *started;
+
+        // starting again does not extend the update
+        feed(&mut terminal, "\x1b[?2026h");
+        assert_eq!(terminal.synchronized_frame.as_ref().unwrap().1, started);
+
+        assert!(!terminal.expire_synchronized_update(started));
+        assert!(terminal.synchronized_frame.is_some());
+        assert!(terminal.expire_synchronized_update(started + SYNCHRONIZED_UPDATE_TIMEOUT));
+        assert!(terminal.synchronized_frame.is_none());
+        assert_eq!(terminal.mode_state(2026, true), Some(false));
+    }
+
+    #[test]
+    fn synchronized_output_only_freezes_the_screen() {
+        let mut terminal = headless_terminal();
+        feed(
+            &mut terminal,
+            "before\x1b[?2026h\x1b[4 q\x1b]4;1;rgb:12/34/56\x07",
+        );
+        terminal.set_focus(false);
+        for line in 0..30 {
+            feed(&mut terminal, &format!("{}\r\n", line));
+        }
+
+        let shown = terminal.rendered_display();
+        assert!(row_text(&shown.cells[0]).starts_with("before"));
+        assert_eq!(shown.cursor_shape, CursorShape::Block);
+        assert!(!shown.focused);
+        assert_eq!(shown.palette.colors[1], (0x12, 0x34, 0x56));
+        assert!(Rc::ptr_eq(&shown.scrollback, &terminal.scrollback));
+    }
+
+    #[test]
+    fn cursor_style_follows_decscusr() {
+        let mut terminal = headless_terminal();
+
+        // (sequence, shape, blinking)
+        let cases = [
+            ("\x1b[2 q", CursorShape::Block, false),
+            ("\x1b[3 q", CursorShape::Underline, true),
+            ("\x1b[4 q", CursorShape::Underline, false),
+            ("\x1b[5 q", CursorShape::Bar, true),
+            ("\x1b[6 q", CursorShape::Bar, false),
+            ("\x1b[1 q", CursorShape::Block, true),
+            ("\x1b[6 q\x1b[ q", CursorShape::Block, true),
+            ("\x1b[4 q\x1b[9 q", CursorShape::Underline, false),
+        ];
+
+        for (sequence, shape, blinking) in cases {
+            feed(&mut terminal, sequence);
+            assert_eq!(terminal.display.cursor_shape, shape, "{:?}", sequence);
+            assert_eq!(terminal.display.cursor_blinking, blinking, "{:?}", sequence);
+        }
+
+        // xtversion still answers
+        feed(&mut terminal, "\x1b[>q");
+        assert!(terminal.take_responses().starts_with(b"\x1bP>|vigil("));
+    }
+
+    #[test]
+    fn osc_sets_the_title() {
+        let mut terminal = headless_terminal();
+
+        // (sequence, title, icon name)
+        let cases = [
+            ("\x1b]0;both\x07", "both", "both"),
+            ("\x1b]2;vim: a;b.txt\x1b\\", "vim: a;b.txt", "both"),
+            ("\x1b]1;icon\x07", "vim: a;b.txt", "icon"),
+            ("\x1b]2;héllo\x07", "héllo", "icon"),
+            ("\x1b]2;\x07", "", "icon"),
+        ];
+
+        for (sequence, title, icon_name) in cases {
+            feed(&mut terminal, sequence);
+            assert_eq!(terminal.title, title, "{:?}", sequence);
+            assert_eq!(terminal.icon_name, icon_name, "{:?}", sequence);
+        }
+    }
+
+    #[test]
+    fn title_changes_are_events() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b]2;one\x07\x1b]2;two\x07");
+        assert_eq!(
+            terminal.take_events(),
+            vec![TerminalEvent::TitleChanged, TerminalEvent::TitleChanged]
+        );
+
+        // setting the same title again or only the icon name does not change the window title
+        feed(&mut terminal, "\x1b]2;two\x07\x1b]1;icon\x07");
+        assert!(terminal.take_events().is_empty());
+    }
+
+    #[test]
+    fn title_stack_saves_and_restores() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b]0;shell\x07\x1b[22;0t\x1b]0;vim\x07");
+        feed(&mut terminal, "\x1b[22;2t\x1b]2;vim - file\x07");
+
+        feed(&mut terminal, "\x1b[23;2t");
+        assert_eq!(
+            (terminal.title.as_str(), terminal.icon_name.as_str()),
+            ("vim", "vim")
+        );
+        feed(&mut terminal, "\x1b[23;0t");
+        assert_eq!(
+            (terminal.title.as_str(), terminal.icon_name.as_str()),
+            ("shell", "shell")
+        );
+
+        // popping an empty stack keeps the title
+        feed(&mut terminal, "\x1b[23t");
+        assert_eq!(terminal.title, "shell");
+
+        // only the title is restored from an entry that saved both
+        feed(&mut terminal, "\x1b[22t\x1b]0;other\x07\x1b[23;2t");
+        assert_eq!(
+            (terminal.title.as_str(), terminal.icon_name.as_str()),
+            ("shell", "other")
+        );
+    }
+
+    #[test]
+    fn title_stack_is_bounded() {
+        let mut terminal = headless_terminal();
+        for index in 0..15 {
+            feed(&mut terminal, &format!("\x1b]2;{}\x07\x1b[22;2t", index));
+        }
+
+        assert_eq!(terminal.title_stack.len(), TITLE_STACK_SIZE);
+        assert_eq!(terminal.title_stack[0].1.as_deref(), Some("5"));
+    }
+
+    #[test]
+    fn dynamic_colors_are_set_and_reported() {
+        let mut terminal = headless_terminal();
+        let query = |terminal: &mut Terminal, sequence: &str| {
+            feed(terminal, sequence);
+            String::from_utf8(terminal.take_responses()).unwrap()
+        };
+
+        // (sequence, expected reply)
+        let cases = [
+            ("\x1b]11;?\x07", "\x1b]11;rgb:0000/0000/0000\x07"),
+            ("\x1b]11;?\x1b\\", "\x1b]11;rgb:0000/0000/0000\x1b\\"),
+            ("\x1b]10;?\x07", "\x1b]10;rgb:ffff/ffff/ffff\x07"),
+            ("\x1b]12;?\x07", "\x1b]12;rgb:ffff/ffff/ffff\x07"),
+            ("\x1b]11;#1e1e2e\x07\x1b]11;?\x07", "\x1b]11;rgb:1e1e/1e1e/2e2e\x07"),
+            ("\x1b]12;rgb:ff/00/00\x07\x1b]12;?\x07", "\x1b]12;rgb:ffff/0000/0000\x07"),
+            // later colors go to the next ones
+            ("\x1b]10;?;?\x07", "\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:1e1e/1e1e/2e2e\x07"),
+            ("\x1b]10;#000000;#ffffff\x07\x1b]10;?;?\x07", "\x1b]10;rgb:0000/0000/0000\x07\x1b]11;rgb:ffff/ffff/ffff\x07"),
+            ("\x1b]110\x07\x1b]111\x07\x1b]112\x07\x1b]10;?;?;?\x07", "\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:0000/0000/0000\x07\x1b]12;rgb:ffff/ffff/ffff\x07"),
+            ("\x1b]11;not a color\x07\x1b]11;?\x07", "\x1b]11;rgb:0000/0000/0000\x07"),
+        ];
+
+        for (sequence, reply) in cases {
+            assert_eq!(query(&mut terminal, sequence), reply, "{:?}", sequence);
+        }
+
+        // the colors are reported as they are, not as reverse video shows them
+        feed(&mut terminal, "\x1b[?5h");
+        assert_eq!(
+            query(&mut terminal, "\x1b]11;?\x07"),
+            "\x1b]11;rgb:0000/0000/0000\x07"
+        );
+    }
+
+    #[test]
+    fn palette_colors_are_set_reported_and_reset() {
+        let mut terminal = headless_terminal();
+        let query = |terminal: &mut Terminal, sequence: &str| {
+            feed(terminal, sequence);
+            String::from_utf8(terminal.take_responses()).unwrap()
+        };
+
+        assert_eq!(
+            query(&mut terminal, "\x1b]4;1;?\x1b\\"),
+            "\x1b]4;1;rgb:cdcd/0000/0000\x1b\\"
+        );
+
+        feed(&mut terminal, "\x1b]4;1;#ff0000;196;rgb:01/02/03\x07");
+        assert_eq!(terminal.display.palette.colors[1], (0xff, 0x00, 0x00));
+        assert_eq!(terminal.display.palette.colors[196], (0x01, 0x02, 0x03));
+        assert_eq!(
+            query(&mut terminal, "\x1b]4;1;?;196;?\x07"),
+            "\x1b]4;1;rgb:ffff/0000/0000\x07\x1b]4;196;rgb:0101/0202/0303\x07"
+        );
+
+        // out of range entries are ignored
+        assert_eq!(query(&mut terminal, "\x1b]4;256;?\x07"), "");
+
+        feed(&mut terminal, "\x1b]104;1\x07");
+        assert_eq!(terminal.display.palette.colors[1], (0xcd, 0x00, 0x00));
+        assert_eq!(terminal.display.palette.colors[196], (0x01, 0x02, 0x03));
+
+        feed(&mut terminal, "\x1b]4;2;#123456\x07\x1b]104\x07");
+        assert_eq!(
+            terminal.display.palette.colors,
+            ColorPalette::default().colors
+        );
+    }
+
+    #[test]
+    fn invalid_palette_resets_do_nothing() {
+        let mut terminal = headless_terminal();
+
+        // (sequence, whether the changed entry is reset)
+        let cases = [
+            ("\x1b]104;999\x07", false),
+            ("\x1b]104;foo\x07", false),
+            ("\x1b]104;foo;2\x07", true),
+            ("\x1b]104;\x07", true),
+            ("\x1b]104\x07", true),
+        ];
+
+        for (sequence, reset) in cases {
+            feed(&mut terminal, "\x1b]4;2;#123456\x07");
+            feed(&mut terminal, sequence);
+            let expected = match reset {
+                true => ColorPalette::default().colors[2],
+                false => (0x12, 0x34, 0x56),
+            };
+            assert_eq!(
+                terminal.display.palette.colors[2], expected,
+                "{:?}",
+                sequence
+            );
+        }
+    }
+
+    #[test]
+    fn editing_sequences_ignore_intermediates() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abcdef\r\nline\x1b[H");
+
+        for sequence in ["\x1b[?2P", "\x1b[?2X", "\x1b[?L", "\x1b[>M", "\x1b[?3b"] {
+            feed(&mut terminal, sequence);
+            assert_eq!(
+                row_text(&terminal.display.cells[0]),
+                "abcdef",
+                "{:?}",
+                sequence
+            );
+            assert_eq!(
+                row_text(&terminal.display.cells[1]),
+                "line",
+                "{:?}",
+                sequence
+            );
+        }
+    }
+
+    #[test]
+    fn repeat_is_bounded_by_the_screen() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "x\x1b[65535b");
+
+        // one more than a screen of the character, the first row scrolled off
+        let printed = terminal
+            .scrollback
+            .iter()
+            .chain(terminal.display.cells.iter())
+            .flat_map(|row| row.iter())
+            .filter(|cell| cell.character == 'x')
+            .count();
+        assert_eq!(printed, terminal.rows * terminal.columns + 1);
+        assert_eq!(terminal.scrollback.len(), 1);
+    }
+
+    #[test]
+    fn tab_sequences_ignore_intermediates() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?3g\x1b[ 3g\x1b[?I\x1b[>2I");
+        assert_eq!(terminal.cursor_x, 0);
+        assert_eq!(terminal.tab_stops, default_tab_stops(0..terminal.columns));
+
+        feed(&mut terminal, "\x1b[1;20H\x1b[?Z\x1b[ Z");
+        assert_eq!(terminal.cursor_x, 19);
+    }
+
+    #[test]
+    fn restored_origin_mode_cursor_stays_in_the_region() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "\x1b[?6h\x1b7\x1b[5;20r\x1b8\x1b[6n");
+
+        assert_eq!(terminal.cursor_y, 4);
+        assert_eq!(terminal.take_responses(), b"\x1b[1;1R");
+    }
+
+    #[test]
+    fn title_stack_ignores_other_window_sequences() {
+        let mut terminal = headless_terminal();
+        feed(
+            &mut terminal,
+            "\x1b]2;saved\x07\x1b[22;2t\x1b]2;current\x07",
+        );
+
+        // XTSMTITLE and friends share the final byte
+        feed(&mut terminal, "\x1b[>23t\x1b[?23t\x1b[23 t");
+        assert_eq!(terminal.title, "current");
+        assert_eq!(terminal.title_stack.len(), 1);
+    }
+
+    #[test]
+    fn input_is_queued_instead_of_blocking() {
+        let mut terminal = headless_terminal();
+        // in the headless terminal the input comes out of the other end of the pair
+        let mut relay = terminal.stdout_stream.try_clone().unwrap();
+
+        let input: Vec<u8> = (0..4 * 1024 * 1024).map(|byte| byte as u8).collect();
+        terminal.write_input(&input).unwrap();
+        assert!(terminal.has_queued_input());
+
+        let mut received = Vec::new();
+        let mut buffer = [0; 65536];
+        while received.len() < input.len() {
+            let read = relay.read(&mut buffer).unwrap();
+            received.extend_from_slice(&buffer[..read]);
+            terminal.flush_input().unwrap();
+        }
+
+        assert_eq!(received, input);
+        assert!(!terminal.has_queued_input());
+    }
+
+    #[test]
+    fn vertical_moves_clear_the_pending_wrap() {
+        // (description, sequence after a full row, where the next character lands)
+        let cases: &[(&str, &str, (usize, usize))] = &[
+            ("line feed", "\n", (79, 5)),
+            ("index", "\x1bD", (79, 5)),
+            ("next line", "\x1bE", (0, 5)),
+            ("reverse index", "\x1bM", (79, 3)),
+            ("scroll up", "\x1b[S", (79, 4)),
+            ("scroll down", "\x1b[T", (79, 4)),
+        ];
+
+        for (description, sequence, (x, y)) in cases {
+            let mut terminal = headless_terminal();
+            feed(
+                &mut terminal,
+                &format!("\x1b[5;1H{}{}y", "x".repeat(80), sequence),
+            );
+            assert_eq!(
+                terminal.display.cells[*y][*x].character, 'y',
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn reflow_does_not_pad_the_cursor_line() {
+        let mut terminal = headless_terminal();
+        feed(&mut terminal, "abc\x1b[1;70H");
+
+        terminal.resize(24, 40);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (39, 0));
+        assert!(!terminal.display.cells[0].wrapped);
+        assert!(row_text(&terminal.display.cells[1]).is_empty());
+
+        // widening again does not join blank rows into the line
+        terminal.resize(24, 80);
+        assert_eq!(row_text(&terminal.display.cells[0]), "abc");
+        assert!(!terminal.display.cells[0].wrapped);
+        assert_eq!(terminal.cursor_y, 0);
     }
 }
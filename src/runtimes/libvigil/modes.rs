@@ -0,0 +1,86 @@
+// modes set by `CSI Pm h` and reset by `CSI Pm l`, DEC private modes have a `?` before their
+// parameters. modes that only flip a flag live in `Modes`, the ones that act on the screen
+// (like the alternate screen) are handled by the terminal itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Modes {
+    flags: usize,
+}
+
+impl Modes {
+    // ansi modes
+    pub const INSERT: usize = 1 << 0;
+    pub const NEWLINE: usize = 1 << 1;
+
+    // dec private modes
+    pub const CURSOR_KEYS: usize = 1 << 2;
+    pub const REVERSE_VIDEO: usize = 1 << 3;
+    pub const ORIGIN: usize = 1 << 4;
+    pub const AUTOWRAP: usize = 1 << 5;
+    pub const CURSOR_VISIBLE: usize = 1 << 6;
+    // mouse tracking, only one of them is enabled at a time
+    pub const MOUSE_NORMAL: usize = 1 << 7;
+    pub const MOUSE_BUTTON_EVENT: usize = 1 << 8;
+    pub const MOUSE_ANY_EVENT: usize = 1 << 9;
+    // mouse encodings, SGR takes priority over urxvt
+    pub const MOUSE_SGR: usize = 1 << 10;
+    pub const MOUSE_URXVT: usize = 1 << 11;
+    pub const BRACKETED_PASTE: usize = 1 << 12;
+    // also set by `ESC =` and reset by `ESC >`
+    pub const KEYPAD_APPLICATION: usize = 1 << 13;
+    // `CSI I` and `CSI O` are sent when the window gains or loses focus
+    pub const FOCUS_EVENTS: usize = 1 << 14;
+    // the screen is not redrawn until the mode is reset again
+    pub const SYNCHRONIZED_OUTPUT: usize = 1 << 15;
+
+    pub const MOUSE_TRACKING: usize =
+        Self::MOUSE_NORMAL | Self::MOUSE_BUTTON_EVENT | Self::MOUSE_ANY_EVENT;
+
+    pub fn has(&self, mode: usize) -> bool {
+        self.flags & mode != 0
+    }
+
+    pub fn set(&mut self, mode: usize, enabled: bool) {
+        if enabled {
+            self.flags |= mode;
+        } else {
+            self.flags &= !mode;
+        }
+    }
+
+    // the flag of a mode number, `None` for the modes that are not simple flags
+    pub fn from_number(number: u16, private: bool) -> Option<usize> {
+        if !private {
+            return match number {
+                4 => Some(Self::INSERT),
+                20 => Some(Self::NEWLINE),
+                _ => None,
+            };
+        }
+
+        match number {
+            1 => Some(Self::CURSOR_KEYS),
+            5 => Some(Self::REVERSE_VIDEO),
+            6 => Some(Self::ORIGIN),
+            7 => Some(Self::AUTOWRAP),
+            25 => Some(Self::CURSOR_VISIBLE),
+            66 => Some(Self::KEYPAD_APPLICATION),
+            1000 => Some(Self::MOUSE_NORMAL),
+            1002 => Some(Self::MOUSE_BUTTON_EVENT),
+            1003 => Some(Self::MOUSE_ANY_EVENT),
+            1006 => Some(Self::MOUSE_SGR),
+            1004 => Some(Self::FOCUS_EVENTS),
+            1015 => Some(Self::MOUSE_URXVT),
+            2004 => Some(Self::BRACKETED_PASTE),
+            2026 => Some(Self::SYNCHRONIZED_OUTPUT),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            flags: Self::AUTOWRAP | Self::CURSOR_VISIBLE,
+        }
+    }
+}
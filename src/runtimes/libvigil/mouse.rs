@@ -0,0 +1,79 @@
+use crate::app::display::{MouseAction, MouseButton, MouseInput};
+
+use super::Modes;
+
+// encodes a mouse event for the enabled tracking mode and encoding, `None` when the event is
+// not tracked or does not fit in the encoding
+pub fn encode(input: MouseInput, modes: Modes) -> Option<Vec<u8>> {
+    let tracked = match input.action {
+        MouseAction::Press(_)
+        | MouseAction::Release(_)
+        | MouseAction::WheelUp
+        | MouseAction::WheelDown => modes.has(Modes::MOUSE_TRACKING),
+        MouseAction::Move(Some(_)) => {
+            modes.has(Modes::MOUSE_BUTTON_EVENT) || modes.has(Modes::MOUSE_ANY_EVENT)
+        }
+        MouseAction::Move(None) => modes.has(Modes::MOUSE_ANY_EVENT),
+    };
+    if !tracked {
+        return None;
+    }
+
+    let mut code = match input.action {
+        MouseAction::Press(button) | MouseAction::Move(Some(button)) => button_code(button),
+        // only sgr says which button was released
+        MouseAction::Release(button) if modes.has(Modes::MOUSE_SGR) => button_code(button),
+        MouseAction::Release(_) => 3,
+        MouseAction::Move(None) => 3,
+        MouseAction::WheelUp => 64,
+        MouseAction::WheelDown => 65,
+    };
+    if matches!(input.action, MouseAction::Move(_)) {
+        code += 32;
+    }
+    if input.shift {
+        code += 4;
+    }
+    if input.alt {
+        code += 8;
+    }
+    if input.control {
+        code += 16;
+    }
+
+    // coordinates are 1 based in every encoding
+    let (column, row) = (input.column + 1, input.row + 1);
+
+    if modes.has(Modes::MOUSE_SGR) {
+        let end = match input.action {
+            MouseAction::Release(_) => 'm',
+            _ => 'M',
+        };
+        return Some(format!("\x1b[<{};{};{}{}", code, column, row, end).into_bytes());
+    }
+
+    if modes.has(Modes::MOUSE_URXVT) {
+        return Some(format!("\x1b[{};{};{}M", code + 32, column, row).into_bytes());
+    }
+
+    // the default encoding sends every value as a single byte offset by 32
+    if column > 223 || row > 223 {
+        return None;
+    }
+    Some(vec![
+        0x1b,
+        b'[',
+        b'M',
+        code + 32,
+        column as u8 + 32,
+        row as u8 + 32,
+    ])
+}
+
+fn button_code(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+    }
+}